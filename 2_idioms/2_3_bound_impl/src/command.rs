@@ -0,0 +1,236 @@
+//! Handling of [`Command`]s, turning them into events of [`Aggregate`]s.
+
+use std::fmt;
+
+use crate::{
+    envelope::EventMetadata,
+    repository::Repository,
+    snapshot::{NeverSnapshot, NoSnapshots, SnapshotPolicy, SnapshotStore},
+    store::{self, EventStore},
+    Aggregate, AggregateEvent, AggregateId, Version,
+};
+
+/// An intent to change an [`Aggregate`].
+pub trait Command<A>
+where
+    A: Aggregate,
+{
+    /// Type of the events the command results in.
+    type Event: AggregateEvent<A>;
+
+    /// Reason of the command being rejected.
+    type Error;
+}
+
+/// Decides whether a [`Command`] is valid for the current state of an [`Aggregate`], and
+/// which events it results in.
+pub trait CommandHandler<A, C>
+where
+    A: Aggregate,
+    C: Command<A>,
+{
+    /// Validates the `command` against the current state of the `aggregate`, returning the
+    /// events to be applied.
+    fn handle(&self, aggregate: &A, command: C) -> Result<Vec<C::Event>, C::Error>;
+}
+
+/// Executes [`Command`]s on [`Entity`]s loaded from a [`Repository`], appending the resulting
+/// events back.
+///
+/// [`Entity`]: crate::Entity
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Dispatcher<H, ES, SS = NoSnapshots, P = NeverSnapshot> {
+    handler: H,
+    repository: Repository<ES, SS, P>,
+}
+
+impl<H, ES, SS, P> Dispatcher<H, ES, SS, P> {
+    /// Creates a new [`Dispatcher`] handling [`Command`]s with the given [`CommandHandler`].
+    pub fn new(handler: H, repository: Repository<ES, SS, P>) -> Self {
+        Self {
+            handler,
+            repository,
+        }
+    }
+
+    /// The underlying [`CommandHandler`].
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// The underlying [`Repository`].
+    pub fn repository(&self) -> &Repository<ES, SS, P> {
+        &self.repository
+    }
+
+    /// Executes the `command` on the aggregate with the given `id`.
+    ///
+    /// Returns the new version of the aggregate.
+    pub fn dispatch<A, I, C>(&self, id: I, command: C) -> Result<Version, DispatchError<C::Error>>
+    where
+        A: Aggregate,
+        I: AggregateId<A>,
+        C: Command<A>,
+        H: CommandHandler<A, C>,
+        ES: EventStore<A, C::Event>,
+        SS: SnapshotStore<A>,
+        P: SnapshotPolicy,
+    {
+        self.dispatch_with(id, command, &EventMetadata::default())
+    }
+
+    /// Executes the `command` on the aggregate with the given `id`, attaching the given
+    /// `metadata` to the resulting events.
+    ///
+    /// Returns the new version of the aggregate.
+    pub fn dispatch_with<A, I, C>(
+        &self,
+        id: I,
+        command: C,
+        metadata: &EventMetadata,
+    ) -> Result<Version, DispatchError<C::Error>>
+    where
+        A: Aggregate,
+        I: AggregateId<A>,
+        C: Command<A>,
+        H: CommandHandler<A, C>,
+        ES: EventStore<A, C::Event>,
+        SS: SnapshotStore<A>,
+        P: SnapshotPolicy,
+    {
+        let mut entity = self.repository.load::<A, C::Event, I>(id)?;
+        let events = self
+            .handler
+            .handle(entity.aggregate().state(), command)
+            .map_err(DispatchError::Rejected)?;
+        Ok(self.repository.append(&mut entity, events, metadata)?)
+    }
+}
+
+/// An error of dispatching a [`Command`].
+#[derive(Debug)]
+pub enum DispatchError<E> {
+    /// [`CommandHandler`] rejected the [`Command`].
+    Rejected(E),
+    /// Loading the aggregate or appending its events failed.
+    ///
+    /// [`store::Error::Conflict`] means the aggregate has been modified concurrently, so the
+    /// [`Command`] may be retried.
+    Store(store::Error),
+}
+
+impl<E> fmt::Display for DispatchError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DispatchError::Rejected(e) => write!(f, "command rejected: {e}"),
+            DispatchError::Store(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl<E> std::error::Error for DispatchError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DispatchError::Rejected(e) => Some(e),
+            DispatchError::Store(e) => Some(e),
+        }
+    }
+}
+
+impl<E> From<store::Error> for DispatchError<E> {
+    fn from(e: store::Error) -> Self {
+        DispatchError::Store(e)
+    }
+}
+
+#[cfg(test)]
+mod dispatcher_spec {
+    use crate::{
+        mock::{Counter, CounterCommand, CounterError, CounterEvent, CounterHandler, CounterId},
+        store::{InMemoryEventStore, Since},
+    };
+
+    use super::*;
+
+    fn dispatcher() -> Dispatcher<CounterHandler, InMemoryEventStore> {
+        Dispatcher::new(CounterHandler, Repository::new(InMemoryEventStore::new()))
+    }
+
+    #[test]
+    fn appends_events_emitted_by_handler() {
+        let dispatcher = dispatcher();
+        let id = CounterId("a".into());
+
+        for (command, expected) in [
+            (CounterCommand::Increment, 1),
+            (CounterCommand::Increment, 2),
+            (CounterCommand::Decrement, 3),
+        ] {
+            let version = dispatcher.dispatch(id.clone(), command).unwrap();
+            assert_eq!(version, Version::new(expected));
+        }
+
+        let events = EventStore::<Counter, CounterEvent>::read_events(
+            dispatcher.repository().events(),
+            &id,
+            Since::BeginningOfStream,
+        )
+        .unwrap();
+        assert_eq!(
+            events.into_iter().map(|e| e.event).collect::<Vec<_>>(),
+            [
+                CounterEvent::Incremented,
+                CounterEvent::Incremented,
+                CounterEvent::Decremented,
+            ],
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_command() {
+        let dispatcher = dispatcher();
+        let id = CounterId("a".into());
+
+        let err = dispatcher
+            .dispatch(id.clone(), CounterCommand::Decrement)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            DispatchError::Rejected(CounterError::BelowZero)
+        ));
+
+        let entity = dispatcher
+            .repository()
+            .load::<Counter, CounterEvent, _>(id)
+            .unwrap();
+        assert_eq!(entity.aggregate().version(), Version::Initial);
+    }
+
+    #[test]
+    fn attaches_metadata_to_events() {
+        let dispatcher = dispatcher();
+        let id = CounterId("a".into());
+
+        dispatcher
+            .dispatch_with(
+                id.clone(),
+                CounterCommand::Increment,
+                &EventMetadata::default().correlation_id("corr"),
+            )
+            .unwrap();
+
+        let events = EventStore::<Counter, CounterEvent>::read_events(
+            dispatcher.repository().events(),
+            &id,
+            Since::BeginningOfStream,
+        )
+        .unwrap();
+        assert_eq!(events[0].metadata.correlation_id.as_deref(), Some("corr"));
+    }
+}
@@ -0,0 +1,357 @@
+//! Self-describing [`EventEnvelope`]s, carrying events along with their metadata.
+
+use std::{collections::BTreeMap, time::SystemTime};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::EventNumber;
+
+/// An event along with everything needed to interpret it without any other context.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EventEnvelope<E> {
+    /// [`Aggregate::aggregate_type()`] of the aggregate the event belongs to.
+    ///
+    /// [`Aggregate::aggregate_type()`]: crate::Aggregate::aggregate_type
+    pub aggregate_type: String,
+    /// [`AggregateId::as_str()`] of the aggregate the event belongs to.
+    ///
+    /// [`AggregateId::as_str()`]: crate::AggregateId::as_str
+    pub aggregate_id: String,
+    /// The number of the event in the stream of its aggregate.
+    pub sequence: EventNumber,
+    /// [`Event::event_type()`] of the event.
+    ///
+    /// [`Event::event_type()`]: crate::Event::event_type
+    pub event_type: String,
+    /// [`Event::schema_version()`] of the event.
+    ///
+    /// [`Event::schema_version()`]: crate::Event::schema_version
+    #[serde(default = "initial_schema_version")]
+    pub schema_version: u32,
+    /// The moment the event was recorded at.
+    #[serde(with = "rfc3339")]
+    pub timestamp: SystemTime,
+    /// Additional information about the event.
+    pub metadata: EventMetadata,
+    /// The event itself.
+    pub event: E,
+}
+
+/// [`EventEnvelope`] with the event kept in its serialized form.
+pub type RawEventEnvelope = EventEnvelope<serde_json::Value>;
+
+/// Additional information attached to events when they're recorded.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EventMetadata {
+    /// Identifier of the whole workflow the event is part of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// Identifier of the message (command or event) that directly caused the event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub causation_id: Option<String>,
+    /// Arbitrary user-defined entries.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub user: BTreeMap<String, String>,
+}
+
+impl EventMetadata {
+    /// Sets the [correlation ID](EventMetadata::correlation_id).
+    pub fn correlation_id(mut self, id: impl Into<String>) -> Self {
+        self.correlation_id = Some(id.into());
+        self
+    }
+
+    /// Sets the [causation ID](EventMetadata::causation_id).
+    pub fn causation_id(mut self, id: impl Into<String>) -> Self {
+        self.causation_id = Some(id.into());
+        self
+    }
+
+    /// Adds the [user-defined](EventMetadata::user) entry.
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.user.insert(key.into(), value.into());
+        self
+    }
+}
+
+impl<E> EventEnvelope<E> {
+    /// Identifier of this event, unique across all streams.
+    ///
+    /// Intended to be used as [causation ID](EventMetadata::causation_id) of the messages
+    /// caused by this event.
+    pub fn event_id(&self) -> String {
+        format!(
+            "{}/{}/{}",
+            self.aggregate_type,
+            self.aggregate_id,
+            self.sequence.get(),
+        )
+    }
+
+    /// Transforms the event of this [`EventEnvelope`], keeping the rest intact.
+    pub fn map<T>(self, f: impl FnOnce(E) -> T) -> EventEnvelope<T> {
+        EventEnvelope {
+            aggregate_type: self.aggregate_type,
+            aggregate_id: self.aggregate_id,
+            sequence: self.sequence,
+            event_type: self.event_type,
+            schema_version: self.schema_version,
+            timestamp: self.timestamp,
+            metadata: self.metadata,
+            event: f(self.event),
+        }
+    }
+}
+
+impl<E> EventEnvelope<E>
+where
+    E: Serialize,
+{
+    /// Serializes this [`EventEnvelope`] into JSON.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    /// Serializes this [`EventEnvelope`] into compact binary format ([MessagePack]), keeping
+    /// the field names, so it's still self-describing.
+    ///
+    /// [MessagePack]: https://msgpack.org
+    pub fn to_binary(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        rmp_serde::to_vec_named(self)
+    }
+
+    /// Converts this [`EventEnvelope`] into a [`RawEventEnvelope`].
+    pub fn to_raw(&self) -> serde_json::Result<RawEventEnvelope> {
+        let event = serde_json::to_value(&self.event)?;
+        Ok(EventEnvelope {
+            aggregate_type: self.aggregate_type.clone(),
+            aggregate_id: self.aggregate_id.clone(),
+            sequence: self.sequence,
+            event_type: self.event_type.clone(),
+            schema_version: self.schema_version,
+            timestamp: self.timestamp,
+            metadata: self.metadata.clone(),
+            event,
+        })
+    }
+}
+
+impl<E> EventEnvelope<E>
+where
+    E: DeserializeOwned,
+{
+    /// Deserializes an [`EventEnvelope`] from JSON.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// Deserializes an [`EventEnvelope`] from the binary format produced by
+    /// [`EventEnvelope::to_binary()`].
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
+        rmp_serde::from_slice(bytes)
+    }
+}
+
+impl RawEventEnvelope {
+    /// Deserializes the event of this [`RawEventEnvelope`].
+    pub fn decode<E>(&self) -> serde_json::Result<EventEnvelope<E>>
+    where
+        E: DeserializeOwned,
+    {
+        let event = E::deserialize(&self.event)?;
+        Ok(self.clone().map(|_| event))
+    }
+}
+
+/// Schema version of the events persisted before schema versioning was introduced.
+fn initial_schema_version() -> u32 {
+    1
+}
+
+/// [RFC 3339] (de)serialization of [`SystemTime`].
+///
+/// Timestamps are written in UTC, but read with any UTC offset.
+///
+/// [RFC 3339]: https://datatracker.ietf.org/doc/html/rfc3339
+mod rfc3339 {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use serde::{de::Error as _, ser::Error as _, Deserialize as _, Deserializer, Serializer};
+
+    /// Last second representable in RFC 3339 (`9999-12-31T23:59:59Z`) since the Unix epoch.
+    const MAX_SECS: u64 = 253_402_300_799;
+
+    pub fn serialize<S: Serializer>(time: &SystemTime, ser: S) -> Result<S::Ok, S::Error> {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(since) if since.as_secs() <= MAX_SECS => {
+                ser.collect_str(&humantime::format_rfc3339_nanos(*time))
+            }
+            Ok(_) => Err(S::Error::custom(
+                "time after year 9999 is not representable",
+            )),
+            Err(_) => Err(S::Error::custom(
+                "time before the Unix epoch is not supported",
+            )),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<SystemTime, D::Error> {
+        parse(&String::deserialize(de)?).map_err(D::Error::custom)
+    }
+
+    /// Parses the given RFC 3339 timestamp with an arbitrary UTC offset.
+    fn parse(s: &str) -> Result<SystemTime, String> {
+        let s = s.to_ascii_uppercase();
+        let Some((local, sign, offset)) = split_offset(&s) else {
+            return humantime::parse_rfc3339(&s).map_err(|e| e.to_string());
+        };
+        let offset = parse_offset(offset).ok_or_else(|| format!("invalid UTC offset in `{s}`"))?;
+        let local = humantime::parse_rfc3339(&format!("{local}Z")).map_err(|e| e.to_string())?;
+        // Local time is ahead of UTC for positive offsets.
+        match sign {
+            b'+' => local.checked_sub(offset),
+            _ => local.checked_add(offset),
+        }
+        .filter(|t| t.duration_since(UNIX_EPOCH).is_ok())
+        .ok_or_else(|| format!("`{s}` is out of the supported range"))
+    }
+
+    /// Splits the given timestamp into its local part, the sign and the `HH:MM` of its
+    /// numeric UTC offset, if it has one.
+    fn split_offset(s: &str) -> Option<(&str, u8, &str)> {
+        let at = s.len().checked_sub(6)?;
+        let sign = *s.as_bytes().get(at)?;
+        (sign == b'+' || sign == b'-').then(|| (&s[..at], sign, &s[at + 1..]))
+    }
+
+    /// Parses the given `HH:MM` UTC offset.
+    fn parse_offset(hh_mm: &str) -> Option<Duration> {
+        let (hh, mm) = hh_mm.split_once(':')?;
+        if hh.len() != 2 || mm.len() != 2 {
+            return None;
+        }
+        let (hh, mm) = (hh.parse::<u64>().ok()?, mm.parse::<u64>().ok()?);
+        (hh < 24 && mm < 60).then(|| Duration::from_secs(hh * 3600 + mm * 60))
+    }
+
+    #[cfg(test)]
+    mod rfc3339_spec {
+        use serde_json::{json, Value};
+
+        use super::*;
+
+        fn to_json(time: SystemTime) -> Result<Value, serde_json::Error> {
+            serialize(&time, serde_json::value::Serializer)
+        }
+
+        fn from_json(s: &str) -> Result<SystemTime, serde_json::Error> {
+            deserialize(json!(s))
+        }
+
+        #[test]
+        fn round_trips_in_utc() {
+            let time = UNIX_EPOCH + Duration::new(1_000_000_000, 123);
+
+            let json = to_json(time).unwrap();
+
+            assert_eq!(json, json!("2001-09-09T01:46:40.000000123Z"));
+            assert_eq!(from_json(json.as_str().unwrap()).unwrap(), time);
+        }
+
+        #[test]
+        fn accepts_utc_offsets() {
+            let time = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+
+            for s in [
+                "2001-09-09T01:46:40Z",
+                "2001-09-09t01:46:40z",
+                "2001-09-09T01:46:40+00:00",
+                "2001-09-09T01:46:40-00:00",
+                "2001-09-09T10:46:40+09:00",
+                "2001-09-08T21:16:40-04:30",
+            ] {
+                assert_eq!(from_json(s).unwrap(), time, "{s}");
+            }
+            assert_eq!(
+                to_json(from_json("2001-09-09T10:46:40+09:00").unwrap()).unwrap(),
+                json!("2001-09-09T01:46:40.000000000Z"),
+            );
+        }
+
+        #[test]
+        fn rejects_malformed_offsets() {
+            for s in [
+                "2001-09-09T01:46:40",
+                "2001-09-09T01:46:40+2:00",
+                "2001-09-09T01:46:40+24:00",
+                "2001-09-09T01:46:40+02:60",
+                "1970-01-01T00:00:00+00:01",
+            ] {
+                assert!(from_json(s).is_err(), "{s}");
+            }
+        }
+
+        #[test]
+        fn refuses_unrepresentable_times() {
+            assert!(to_json(UNIX_EPOCH - Duration::from_secs(1)).is_err());
+            assert!(to_json(UNIX_EPOCH + Duration::from_secs(MAX_SECS + 1)).is_err());
+            assert!(to_json(UNIX_EPOCH + Duration::from_secs(MAX_SECS)).is_ok());
+        }
+    }
+}
+
+#[cfg(test)]
+mod event_envelope_spec {
+    use std::time::Duration;
+
+    use crate::mock::CounterEvent;
+
+    use super::*;
+
+    fn envelope() -> EventEnvelope<CounterEvent> {
+        EventEnvelope {
+            aggregate_type: "counter".into(),
+            aggregate_id: "a".into(),
+            sequence: EventNumber::new(7).unwrap(),
+            event_type: "counter_incremented".into(),
+            schema_version: 1,
+            timestamp: SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789),
+            metadata: EventMetadata::default()
+                .correlation_id("corr")
+                .causation_id("cause")
+                .with("user", "alice"),
+            event: CounterEvent::Incremented,
+        }
+    }
+
+    #[test]
+    fn roundtrips_through_json() {
+        let json = envelope().to_json().unwrap();
+
+        assert_eq!(EventEnvelope::from_json(&json).unwrap(), envelope());
+    }
+
+    #[test]
+    fn roundtrips_through_binary() {
+        let bytes = envelope().to_binary().unwrap();
+
+        assert_eq!(EventEnvelope::from_binary(&bytes).unwrap(), envelope());
+        assert!(bytes.len() < envelope().to_json().unwrap().len());
+    }
+
+    #[test]
+    fn is_self_describing() {
+        let json: serde_json::Value = serde_json::from_str(&envelope().to_json().unwrap()).unwrap();
+
+        assert_eq!(json["aggregate_type"], "counter");
+        assert_eq!(json["event_type"], "counter_incremented");
+        assert_eq!(json["sequence"], 7);
+        assert_eq!(json["timestamp"], "2023-11-14T22:13:20.123456789Z");
+        assert_eq!(json["metadata"]["correlation_id"], "corr");
+        assert_eq!(json["metadata"]["user"]["user"], "alice");
+
+        let raw: RawEventEnvelope =
+            EventEnvelope::from_binary(&envelope().to_binary().unwrap()).unwrap();
+        assert_eq!(raw.decode::<CounterEvent>().unwrap(), envelope());
+    }
+}
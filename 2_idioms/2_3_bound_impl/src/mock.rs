@@ -0,0 +1,131 @@
+//! Simple [`Aggregate`]s to be used in tests.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    command::{Command, CommandHandler},
+    Aggregate, AggregateEvent, AggregateId, Event,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Counter {
+    pub value: i64,
+}
+
+impl Aggregate for Counter {
+    fn aggregate_type() -> &'static str {
+        "counter"
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct CounterId(pub String);
+
+impl AggregateId<Counter> for CounterId {
+    fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CounterEvent {
+    Incremented,
+    Decremented,
+}
+
+impl Event for CounterEvent {
+    fn event_type(&self) -> &'static str {
+        match self {
+            CounterEvent::Incremented => "counter_incremented",
+            CounterEvent::Decremented => "counter_decremented",
+        }
+    }
+
+    /// Events of the first schema version were named `Inc` and `Dec`.
+    fn schema_version(&self) -> u32 {
+        2
+    }
+}
+
+impl AggregateEvent<Counter> for CounterEvent {
+    fn apply_to(self, aggregate: &mut Counter) {
+        match self {
+            CounterEvent::Incremented => aggregate.value += 1,
+            CounterEvent::Decremented => aggregate.value -= 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CounterCommand {
+    Increment,
+    Decrement,
+}
+
+impl Command<Counter> for CounterCommand {
+    type Event = CounterEvent;
+    type Error = CounterError;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CounterError {
+    BelowZero,
+}
+
+impl fmt::Display for CounterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CounterError::BelowZero => write!(f, "counter cannot go below zero"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CounterHandler;
+
+impl CommandHandler<Counter, CounterCommand> for CounterHandler {
+    fn handle(
+        &self,
+        counter: &Counter,
+        command: CounterCommand,
+    ) -> Result<Vec<CounterEvent>, CounterError> {
+        match command {
+            CounterCommand::Increment => Ok(vec![CounterEvent::Incremented]),
+            CounterCommand::Decrement if counter.value > 0 => Ok(vec![CounterEvent::Decremented]),
+            CounterCommand::Decrement => Err(CounterError::BelowZero),
+        }
+    }
+}
+
+/// [`Aggregate`] whose events are mixed with the [`Counter`] ones in a global log.
+#[derive(Debug, Default)]
+pub struct Light;
+
+impl Aggregate for Light {
+    fn aggregate_type() -> &'static str {
+        "light"
+    }
+}
+
+pub struct LightId(pub &'static str);
+
+impl AggregateId<Light> for LightId {
+    fn as_str(&self) -> &str {
+        self.0
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Toggled;
+
+impl Event for Toggled {
+    fn event_type(&self) -> &'static str {
+        "light_toggled"
+    }
+}
+
+impl AggregateEvent<Light> for Toggled {
+    fn apply_to(self, _: &mut Light) {}
+}
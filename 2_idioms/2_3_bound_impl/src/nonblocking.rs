@@ -0,0 +1,251 @@
+//! Async counterparts of [`Repository`] operations, usable from [`tokio`] tasks without
+//! blocking the runtime.
+
+use std::{io, panic, sync::Arc};
+
+use crate::{
+    envelope::EventMetadata,
+    repository::Repository,
+    snapshot::{NeverSnapshot, NoSnapshots, Snapshot, SnapshotPolicy, SnapshotStore},
+    store::{Error, EventStore},
+    Aggregate, AggregateEvent, AggregateId, Entity, Version,
+};
+
+/// Shared [`Repository`] performing its blocking I/O on the [`tokio`] blocking thread pool.
+///
+/// The wrapped [`Repository`] remains accessible for synchronous use via
+/// [`AsyncRepository::sync()`].
+#[derive(Debug, Default)]
+pub struct AsyncRepository<ES, SS = NoSnapshots, P = NeverSnapshot> {
+    inner: Arc<Repository<ES, SS, P>>,
+}
+
+impl<ES, SS, P> Clone for AsyncRepository<ES, SS, P> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<ES, SS, P> From<Repository<ES, SS, P>> for AsyncRepository<ES, SS, P> {
+    fn from(repository: Repository<ES, SS, P>) -> Self {
+        Self::new(Arc::new(repository))
+    }
+}
+
+impl<ES, SS, P> AsyncRepository<ES, SS, P> {
+    /// Creates a new [`AsyncRepository`] sharing the given [`Repository`].
+    pub fn new(repository: Arc<Repository<ES, SS, P>>) -> Self {
+        Self { inner: repository }
+    }
+
+    /// The wrapped [`Repository`], for synchronous use.
+    pub fn sync(&self) -> &Arc<Repository<ES, SS, P>> {
+        &self.inner
+    }
+}
+
+impl<ES, SS, P> AsyncRepository<ES, SS, P>
+where
+    ES: Send + Sync + 'static,
+    SS: Send + Sync + 'static,
+    P: SnapshotPolicy + Send + Sync + 'static,
+{
+    /// Loads the [`Entity`] with the given `id`, see [`Repository::load()`].
+    pub async fn load<A, E, I>(&self, id: I) -> Result<Entity<I, A>, Error>
+    where
+        A: Aggregate + Send + 'static,
+        E: AggregateEvent<A>,
+        I: AggregateId<A> + Send + 'static,
+        ES: EventStore<A, E>,
+        SS: SnapshotStore<A>,
+    {
+        let repository = self.inner.clone();
+        blocking(move || repository.load::<A, E, I>(id)).await
+    }
+
+    /// Appends the `events` to the given [`Entity`], see [`Repository::append()`].
+    ///
+    /// Returns the [`Entity`] with the `events` applied. On failure the [`Entity`] is
+    /// dropped, as it has to be reloaded anyway.
+    pub async fn append<A, E, I>(
+        &self,
+        mut entity: Entity<I, A>,
+        events: Vec<E>,
+        metadata: EventMetadata,
+    ) -> Result<Entity<I, A>, Error>
+    where
+        A: Aggregate + Send + 'static,
+        E: AggregateEvent<A> + Send + 'static,
+        I: AggregateId<A> + Send + 'static,
+        ES: EventStore<A, E>,
+        SS: SnapshotStore<A>,
+    {
+        let repository = self.inner.clone();
+        blocking(move || {
+            repository.append(&mut entity, events, &metadata)?;
+            Ok(entity)
+        })
+        .await
+    }
+
+    /// Appends the `events` right to the stream of the aggregate with the given `id`, see
+    /// [`EventStore::append_events()`].
+    pub async fn append_events<A, E, I>(
+        &self,
+        id: I,
+        events: Vec<E>,
+        expected_version: Option<Version>,
+        metadata: EventMetadata,
+    ) -> Result<Version, Error>
+    where
+        A: Aggregate,
+        E: AggregateEvent<A> + Send + 'static,
+        I: AggregateId<A> + Send + 'static,
+        ES: EventStore<A, E>,
+    {
+        let repository = self.inner.clone();
+        blocking(move || {
+            repository
+                .events()
+                .append_events(&id, &events, expected_version, &metadata)
+        })
+        .await
+    }
+
+    /// Reads the latest [`Snapshot`] of the aggregate with the given `id`, see
+    /// [`SnapshotStore::load_snapshot()`].
+    pub async fn load_snapshot<A, I>(&self, id: I) -> Result<Option<Snapshot<A>>, Error>
+    where
+        A: Aggregate + Send + 'static,
+        I: AggregateId<A> + Send + 'static,
+        SS: SnapshotStore<A>,
+    {
+        let repository = self.inner.clone();
+        blocking(move || repository.snapshots().load_snapshot(&id)).await
+    }
+}
+
+/// Runs the blocking `f` on the [`tokio`] blocking thread pool, propagating its panics.
+///
+/// Fails with [`io::ErrorKind::Interrupted`] if the task is cancelled, e.g. on the runtime
+/// shutdown.
+async fn blocking<F, T>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(out) => out,
+        Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+        Err(e) => Err(Error::Io(io::Error::new(io::ErrorKind::Interrupted, e))),
+    }
+}
+
+#[cfg(test)]
+mod async_repository_spec {
+    use std::num::NonZeroU64;
+
+    use crate::{
+        mock::{Counter, CounterEvent, CounterId},
+        snapshot::{EveryNEvents, InMemorySnapshotStore},
+        store::InMemoryEventStore,
+    };
+
+    use super::*;
+
+    fn repository() -> AsyncRepository<InMemoryEventStore, InMemorySnapshotStore, EveryNEvents> {
+        Repository::new(InMemoryEventStore::new())
+            .with_snapshots(
+                InMemorySnapshotStore::new(),
+                EveryNEvents(NonZeroU64::new(2).unwrap()),
+            )
+            .into()
+    }
+
+    #[tokio::test]
+    async fn loads_and_appends_without_blocking() {
+        let repo = repository();
+        let id = CounterId("a".into());
+
+        let entity = repo
+            .load::<Counter, CounterEvent, _>(id.clone())
+            .await
+            .unwrap();
+        let entity = repo
+            .append(
+                entity,
+                vec![CounterEvent::Incremented; 3],
+                EventMetadata::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(entity.aggregate().state().value, 3);
+        assert_eq!(entity.aggregate().version(), Version::new(3));
+
+        let snapshot = repo
+            .load_snapshot::<Counter, _>(id.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshot.version, Version::new(3));
+
+        let entity = repo.sync().load::<Counter, CounterEvent, _>(id).unwrap();
+        assert_eq!(entity.aggregate().state().value, 3);
+    }
+
+    #[tokio::test]
+    async fn appends_events_with_version_check() {
+        let repo = repository();
+        let id = CounterId("a".into());
+
+        let version = repo
+            .append_events(
+                id.clone(),
+                vec![CounterEvent::Incremented],
+                Some(Version::Initial),
+                EventMetadata::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(version, Version::new(1));
+
+        let err = repo
+            .append_events(
+                id,
+                vec![CounterEvent::Decremented],
+                Some(Version::Initial),
+                EventMetadata::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::Conflict(c) if c.actual == Version::new(1)));
+    }
+
+    #[tokio::test]
+    async fn can_be_shared_between_tasks() {
+        let repo = repository();
+
+        let tasks = ["a", "b", "c"].map(|id| {
+            let repo = repo.clone();
+            tokio::spawn(async move {
+                repo.append_events(
+                    CounterId(id.into()),
+                    vec![CounterEvent::Incremented],
+                    None,
+                    EventMetadata::default(),
+                )
+                .await
+            })
+        });
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        assert_eq!(
+            EventStore::<Counter, CounterEvent>::stream_ids(repo.sync().events()).unwrap(),
+            ["a", "b", "c"],
+        );
+    }
+}
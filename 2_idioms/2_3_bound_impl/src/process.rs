@@ -0,0 +1,679 @@
+//! Process managers, coordinating workflows spanning several [`Aggregate`]s.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::RwLock,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    command::{Command, CommandHandler, DispatchError, Dispatcher},
+    envelope::{EventEnvelope, EventMetadata},
+    snapshot::{SnapshotPolicy, SnapshotStore},
+    store::{Error, EventStore, Since},
+    Aggregate, AggregateEvent, EventNumber,
+};
+
+/// Reacts to the events of one [`Aggregate`] by issuing [`Command`]s to another one.
+///
+/// Every instance of a process is identified by its correlation ID and has its own
+/// persisted [`ProcessManager::State`].
+pub trait ProcessManager {
+    /// The [`Aggregate`] whose events the process reacts to.
+    type Aggregate: Aggregate;
+
+    /// The events the process reacts to.
+    type Event: AggregateEvent<Self::Aggregate>;
+
+    /// The [`Aggregate`] the process issues [`Command`]s to.
+    type Target: Aggregate;
+
+    /// The [`Command`]s the process issues.
+    ///
+    /// Serializable to be kept in the [`ProcessRecord::outbox`] until dispatched, and
+    /// cloneable to be kept in the [`ProcessRecord::dead_letters`] once rejected.
+    type Command: Command<Self::Target> + Clone + Serialize + DeserializeOwned;
+
+    /// State of a single process instance.
+    type State: Default + Serialize + DeserializeOwned;
+
+    /// Returns the correlation ID of the process instance the `event` belongs to, or `None`
+    /// if the process is not interested in it.
+    fn correlation_id(&self, event: &EventEnvelope<Self::Event>) -> Option<String>;
+
+    /// Reacts to the `event`, updating the `state` of the process instance and returning the
+    /// [`Command`]s to be issued, along with the IDs of the aggregates to issue them to.
+    fn handle(
+        &self,
+        state: &mut Self::State,
+        event: &EventEnvelope<Self::Event>,
+    ) -> Vec<(String, Self::Command)>;
+}
+
+/// Persisted state of a single process instance.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProcessRecord<S, C> {
+    /// The [`ProcessManager::State`] of the instance.
+    pub state: S,
+    /// Numbers of the last events handled by the instance, per each source aggregate.
+    pub handled: BTreeMap<String, EventNumber>,
+    /// [`Command`]s issued by the instance, but not dispatched yet, in the issuing order.
+    pub outbox: VecDeque<Outgoing<C>>,
+    /// [`Command`]s issued by the instance, but rejected by their target, in the rejection
+    /// order.
+    pub dead_letters: Vec<Outgoing<C>>,
+}
+
+impl<S: Default, C> Default for ProcessRecord<S, C> {
+    fn default() -> Self {
+        Self {
+            state: S::default(),
+            handled: BTreeMap::new(),
+            outbox: VecDeque::new(),
+            dead_letters: Vec::new(),
+        }
+    }
+}
+
+impl<S, C> ProcessRecord<S, C> {
+    /// Checks whether the given event has already been handled by the process instance.
+    pub fn has_handled<E>(&self, event: &EventEnvelope<E>) -> bool {
+        self.handled
+            .get(&event.aggregate_id)
+            .is_some_and(|last| event.sequence <= *last)
+    }
+}
+
+/// [`Command`] issued by a process instance, waiting to be dispatched.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Outgoing<C> {
+    /// ID of the aggregate to dispatch the [`Command`] to.
+    pub aggregate_id: String,
+    /// The [`Command`] itself.
+    pub command: C,
+    /// ID of the event the [`Command`] was issued in reaction to.
+    pub causation_id: String,
+}
+
+/// A place where [`ProcessRecord`]s are kept, keyed by correlation IDs.
+pub trait ProcessStore {
+    /// Loads the record of the process instance with the given `correlation_id`, if any.
+    fn load<S, C>(&self, correlation_id: &str) -> Result<Option<ProcessRecord<S, C>>, Error>
+    where
+        S: DeserializeOwned,
+        C: DeserializeOwned;
+
+    /// Stores the record of the process instance with the given `correlation_id`.
+    fn save<S, C>(&self, correlation_id: &str, record: &ProcessRecord<S, C>) -> Result<(), Error>
+    where
+        S: Serialize,
+        C: Serialize;
+}
+
+/// [`ProcessStore`] keeping records in memory.
+#[derive(Debug, Default)]
+pub struct InMemoryProcessStore {
+    records: RwLock<HashMap<String, serde_json::Value>>,
+}
+
+impl InMemoryProcessStore {
+    /// Creates a new empty [`InMemoryProcessStore`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ProcessStore for InMemoryProcessStore {
+    fn load<S, C>(&self, correlation_id: &str) -> Result<Option<ProcessRecord<S, C>>, Error>
+    where
+        S: DeserializeOwned,
+        C: DeserializeOwned,
+    {
+        self.records
+            .read()
+            .unwrap()
+            .get(correlation_id)
+            .map(|r| Ok(ProcessRecord::deserialize(r)?))
+            .transpose()
+    }
+
+    fn save<S, C>(&self, correlation_id: &str, record: &ProcessRecord<S, C>) -> Result<(), Error>
+    where
+        S: Serialize,
+        C: Serialize,
+    {
+        let record = serde_json::to_value(record)?;
+        self.records
+            .write()
+            .unwrap()
+            .insert(correlation_id.to_owned(), record);
+        Ok(())
+    }
+}
+
+/// Error of handling an event by a [`ProcessManager`], caused by dispatching one of the
+/// [`Command`]s it issued.
+pub type ProcessError<M> = DispatchError<
+    <<M as ProcessManager>::Command as Command<<M as ProcessManager>::Target>>::Error,
+>;
+
+/// Outcome of feeding an event to a [`ProcessRunner`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Handled {
+    /// The [`ProcessManager`] is not interested in the event.
+    Ignored,
+    /// The event has already been handled before, so nothing was done.
+    Duplicate,
+    /// The event has been handled, resulting in the given number of issued [`Command`]s.
+    Commands(usize),
+}
+
+/// Feeds events to a [`ProcessManager`], persisting its instances' state in a
+/// [`ProcessStore`] and dispatching the issued [`Command`]s.
+#[derive(Debug)]
+pub struct ProcessRunner<M, PS> {
+    manager: M,
+    store: PS,
+}
+
+impl<M, PS> ProcessRunner<M, PS> {
+    /// Creates a new [`ProcessRunner`] for the given [`ProcessManager`].
+    pub fn new(manager: M, store: PS) -> Self {
+        Self { manager, store }
+    }
+
+    /// The [`ProcessManager`] being run.
+    pub fn manager(&self) -> &M {
+        &self.manager
+    }
+
+    /// The underlying [`ProcessStore`].
+    pub fn store(&self) -> &PS {
+        &self.store
+    }
+}
+
+impl<M, PS> ProcessRunner<M, PS>
+where
+    M: ProcessManager,
+    PS: ProcessStore,
+{
+    /// Feeds the `event` to the [`ProcessManager`], dispatching the issued [`Command`]s with
+    /// the given [`Dispatcher`].
+    ///
+    /// The issued commands are correlated with the process instance, and caused by the
+    /// `event`. They are persisted in the [`ProcessRecord::outbox`] along with the new state
+    /// of the instance before being dispatched, and removed from there one by one once
+    /// dispatched. So, on failure, the `event` may be fed again (as well as any other event
+    /// of the instance), dispatching only the commands left in the outbox.
+    ///
+    /// Only [`DispatchError::Store`] failures are retried this way: the commands rejected by
+    /// their target are moved to the [`ProcessRecord::dead_letters`] instead, so they don't
+    /// block the instance.
+    ///
+    /// A command may still be dispatched twice if saving the record fails right after
+    /// dispatching it.
+    pub fn handle<H, ES, SS, P>(
+        &self,
+        event: &EventEnvelope<M::Event>,
+        dispatcher: &Dispatcher<H, ES, SS, P>,
+    ) -> Result<Handled, ProcessError<M>>
+    where
+        H: CommandHandler<M::Target, M::Command>,
+        ES: EventStore<M::Target, <M::Command as Command<M::Target>>::Event>,
+        SS: SnapshotStore<M::Target>,
+        P: SnapshotPolicy,
+    {
+        let Some(correlation_id) = self.manager.correlation_id(event) else {
+            return Ok(Handled::Ignored);
+        };
+        let mut record = self
+            .store
+            .load::<M::State, M::Command>(&correlation_id)?
+            .unwrap_or_default();
+        // Commands left from a previous failure go first, preserving the issuing order.
+        self.flush(&correlation_id, &mut record, dispatcher)?;
+        if record.has_handled(event) {
+            return Ok(Handled::Duplicate);
+        }
+
+        let commands = self.manager.handle(&mut record.state, event);
+        let issued = commands.len();
+        record.outbox.extend(
+            commands
+                .into_iter()
+                .map(|(aggregate_id, command)| Outgoing {
+                    aggregate_id,
+                    command,
+                    causation_id: event.event_id(),
+                }),
+        );
+        record
+            .handled
+            .insert(event.aggregate_id.clone(), event.sequence);
+        self.store.save(&correlation_id, &record)?;
+
+        self.flush(&correlation_id, &mut record, dispatcher)?;
+        Ok(Handled::Commands(issued))
+    }
+
+    /// Dispatches the [`Command`]s from the [`ProcessRecord::outbox`] in order, saving the
+    /// `record` after each of them, and moving the rejected ones to the
+    /// [`ProcessRecord::dead_letters`].
+    fn flush<H, ES, SS, P>(
+        &self,
+        correlation_id: &str,
+        record: &mut ProcessRecord<M::State, M::Command>,
+        dispatcher: &Dispatcher<H, ES, SS, P>,
+    ) -> Result<(), ProcessError<M>>
+    where
+        H: CommandHandler<M::Target, M::Command>,
+        ES: EventStore<M::Target, <M::Command as Command<M::Target>>::Event>,
+        SS: SnapshotStore<M::Target>,
+        P: SnapshotPolicy,
+    {
+        while let Some(outgoing) = record.outbox.front() {
+            let metadata = EventMetadata::default()
+                .correlation_id(correlation_id)
+                .causation_id(&outgoing.causation_id);
+            let dispatched = dispatcher.dispatch_with(
+                outgoing.aggregate_id.clone(),
+                outgoing.command.clone(),
+                &metadata,
+            );
+            let outgoing = record.outbox.pop_front().unwrap();
+            match dispatched {
+                Ok(_) => {}
+                Err(DispatchError::Rejected(_)) => record.dead_letters.push(outgoing),
+                Err(e @ DispatchError::Store(_)) => return Err(e),
+            }
+            self.store.save(correlation_id, record)?;
+        }
+        Ok(())
+    }
+
+    /// Feeds all the events of the given [`EventStore`] to the [`ProcessManager`], skipping
+    /// the already handled ones.
+    ///
+    /// Returns the number of newly handled events.
+    pub fn catch_up<S, H, ES, SS, P>(
+        &self,
+        source: &S,
+        dispatcher: &Dispatcher<H, ES, SS, P>,
+    ) -> Result<usize, ProcessError<M>>
+    where
+        S: EventStore<M::Aggregate, M::Event>,
+        H: CommandHandler<M::Target, M::Command>,
+        ES: EventStore<M::Target, <M::Command as Command<M::Target>>::Event>,
+        SS: SnapshotStore<M::Target>,
+        P: SnapshotPolicy,
+    {
+        let mut handled = 0;
+        for id in source.stream_ids()? {
+            for event in source.read_events(&id, Since::BeginningOfStream)? {
+                if let Handled::Commands(_) = self.handle(&event, dispatcher)? {
+                    handled += 1;
+                }
+            }
+        }
+        Ok(handled)
+    }
+}
+
+#[cfg(test)]
+mod process_runner_spec {
+    use std::cell::Cell;
+
+    use crate::{
+        mock::{Counter, CounterEvent, CounterId},
+        repository::Repository,
+        store::InMemoryEventStore,
+        AggregateId, Event, Version,
+    };
+
+    use super::*;
+
+    /// Milestones reached by a counter.
+    #[derive(Debug, Default)]
+    struct Milestones {
+        reached: Vec<u64>,
+    }
+
+    impl Aggregate for Milestones {
+        fn aggregate_type() -> &'static str {
+            "milestones"
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+    struct MilestoneReached(u64);
+
+    impl Event for MilestoneReached {
+        fn event_type(&self) -> &'static str {
+            "milestone_reached"
+        }
+    }
+
+    impl AggregateEvent<Milestones> for MilestoneReached {
+        fn apply_to(self, aggregate: &mut Milestones) {
+            aggregate.reached.push(self.0);
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+    struct RecordMilestone(u64);
+
+    impl Command<Milestones> for RecordMilestone {
+        type Event = MilestoneReached;
+        type Error = &'static str;
+    }
+
+    /// Records milestones, rejecting the given one.
+    #[derive(Default)]
+    struct MilestonesHandler {
+        reject: Option<u64>,
+    }
+
+    impl CommandHandler<Milestones, RecordMilestone> for MilestonesHandler {
+        fn handle(
+            &self,
+            _: &Milestones,
+            cmd: RecordMilestone,
+        ) -> Result<Vec<MilestoneReached>, &'static str> {
+            if self.reject == Some(cmd.0) {
+                return Err("rejected");
+            }
+            Ok(vec![MilestoneReached(cmd.0)])
+        }
+    }
+
+    /// Records a milestone every 2 increments of a counter.
+    struct EveryTwoIncrements;
+
+    impl ProcessManager for EveryTwoIncrements {
+        type Aggregate = Counter;
+        type Event = CounterEvent;
+        type Target = Milestones;
+        type Command = RecordMilestone;
+        type State = u64;
+
+        fn correlation_id(&self, event: &EventEnvelope<CounterEvent>) -> Option<String> {
+            (event.event == CounterEvent::Incremented).then(|| event.aggregate_id.clone())
+        }
+
+        fn handle(
+            &self,
+            increments: &mut u64,
+            event: &EventEnvelope<CounterEvent>,
+        ) -> Vec<(String, RecordMilestone)> {
+            *increments += 1;
+            if increments.is_multiple_of(2) {
+                vec![(event.aggregate_id.clone(), RecordMilestone(*increments))]
+            } else {
+                vec![]
+            }
+        }
+    }
+
+    /// Records two milestones on every increment of a counter.
+    struct TwoPerIncrement;
+
+    impl ProcessManager for TwoPerIncrement {
+        type Aggregate = Counter;
+        type Event = CounterEvent;
+        type Target = Milestones;
+        type Command = RecordMilestone;
+        type State = u64;
+
+        fn correlation_id(&self, event: &EventEnvelope<CounterEvent>) -> Option<String> {
+            (event.event == CounterEvent::Incremented).then(|| event.aggregate_id.clone())
+        }
+
+        fn handle(
+            &self,
+            increments: &mut u64,
+            event: &EventEnvelope<CounterEvent>,
+        ) -> Vec<(String, RecordMilestone)> {
+            *increments += 1;
+            let id = event.aggregate_id.clone();
+            vec![
+                (id.clone(), RecordMilestone(*increments * 10 + 1)),
+                (id, RecordMilestone(*increments * 10 + 2)),
+            ]
+        }
+    }
+
+    fn counter_events(events: &[CounterEvent]) -> InMemoryEventStore {
+        let store = InMemoryEventStore::new();
+        store
+            .append_events(
+                &CounterId("a".into()),
+                events,
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+        store
+    }
+
+    /// [`InMemoryEventStore`] failing the given append once.
+    #[derive(Default)]
+    struct FlakyStore {
+        inner: InMemoryEventStore,
+        appends_before_failure: Cell<Option<u32>>,
+    }
+
+    impl EventStore<Milestones, MilestoneReached> for FlakyStore {
+        fn append_events<I>(
+            &self,
+            id: &I,
+            events: &[MilestoneReached],
+            expected_version: Option<Version>,
+            metadata: &EventMetadata,
+        ) -> Result<Version, Error>
+        where
+            I: AggregateId<Milestones>,
+        {
+            match self.appends_before_failure.get() {
+                Some(0) => {
+                    self.appends_before_failure.set(None);
+                    return Err(Error::Io(std::io::Error::other("disk full")));
+                }
+                Some(n) => self.appends_before_failure.set(Some(n - 1)),
+                None => {}
+            }
+            self.inner
+                .append_events(id, events, expected_version, metadata)
+        }
+
+        fn read_events<I>(
+            &self,
+            id: &I,
+            since: Since,
+        ) -> Result<Vec<EventEnvelope<MilestoneReached>>, Error>
+        where
+            I: AggregateId<Milestones>,
+        {
+            self.inner.read_events(id, since)
+        }
+
+        fn stream_ids(&self) -> Result<Vec<String>, Error> {
+            EventStore::<Milestones, MilestoneReached>::stream_ids(&self.inner)
+        }
+    }
+
+    fn milestones<ES>(
+        dispatcher: &Dispatcher<MilestonesHandler, ES>,
+    ) -> Vec<EventEnvelope<MilestoneReached>>
+    where
+        ES: EventStore<Milestones, MilestoneReached>,
+    {
+        dispatcher
+            .repository()
+            .events()
+            .read_events(&"a".to_owned(), Since::BeginningOfStream)
+            .unwrap()
+    }
+
+    fn reached<ES>(dispatcher: &Dispatcher<MilestonesHandler, ES>) -> Vec<u64>
+    where
+        ES: EventStore<Milestones, MilestoneReached>,
+    {
+        milestones(dispatcher)
+            .into_iter()
+            .map(|e| e.event.0)
+            .collect()
+    }
+
+    #[test]
+    fn issues_commands_with_correlation_and_causation() {
+        let source = counter_events(&[
+            CounterEvent::Incremented,
+            CounterEvent::Decremented,
+            CounterEvent::Incremented,
+        ]);
+        let dispatcher = Dispatcher::new(
+            MilestonesHandler::default(),
+            Repository::new(InMemoryEventStore::new()),
+        );
+        let runner = ProcessRunner::new(EveryTwoIncrements, InMemoryProcessStore::new());
+
+        assert_eq!(runner.catch_up(&source, &dispatcher).unwrap(), 2);
+
+        let milestones = milestones(&dispatcher);
+        assert_eq!(milestones.len(), 1);
+        assert_eq!(milestones[0].event, MilestoneReached(2));
+        assert_eq!(milestones[0].metadata.correlation_id.as_deref(), Some("a"));
+        assert_eq!(
+            milestones[0].metadata.causation_id.as_deref(),
+            Some("counter/a/3")
+        );
+
+        let record = runner
+            .store()
+            .load::<u64, RecordMilestone>("a")
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.state, 2);
+        assert_eq!(record.handled["a"], EventNumber::new(3).unwrap());
+    }
+
+    #[test]
+    fn skips_already_handled_events() {
+        let source = counter_events(&[CounterEvent::Incremented; 2]);
+        let dispatcher = Dispatcher::new(
+            MilestonesHandler::default(),
+            Repository::new(InMemoryEventStore::new()),
+        );
+        let runner = ProcessRunner::new(EveryTwoIncrements, InMemoryProcessStore::new());
+        runner.catch_up(&source, &dispatcher).unwrap();
+
+        let events: Vec<EventEnvelope<CounterEvent>> = source
+            .read_events(&CounterId("a".into()), Since::BeginningOfStream)
+            .unwrap();
+        for event in &events {
+            assert_eq!(
+                runner.handle(event, &dispatcher).unwrap(),
+                Handled::Duplicate
+            );
+        }
+        assert_eq!(runner.catch_up(&source, &dispatcher).unwrap(), 0);
+        assert_eq!(milestones(&dispatcher).len(), 1);
+    }
+
+    #[test]
+    fn ignores_irrelevant_events() {
+        let source = counter_events(&[CounterEvent::Decremented]);
+        let dispatcher = Dispatcher::new(
+            MilestonesHandler::default(),
+            Repository::new(InMemoryEventStore::new()),
+        );
+        let runner = ProcessRunner::new(EveryTwoIncrements, InMemoryProcessStore::new());
+
+        let events: Vec<EventEnvelope<CounterEvent>> = source
+            .read_events(&CounterId("a".into()), Since::BeginningOfStream)
+            .unwrap();
+        assert_eq!(
+            runner.handle(&events[0], &dispatcher).unwrap(),
+            Handled::Ignored
+        );
+        assert_eq!(
+            runner.store().load::<u64, RecordMilestone>("a").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn dispatches_only_pending_commands_on_retry() {
+        let source = counter_events(&[CounterEvent::Incremented]);
+        let dispatcher = Dispatcher::new(
+            MilestonesHandler::default(),
+            Repository::new(FlakyStore {
+                appends_before_failure: Cell::new(Some(1)),
+                ..FlakyStore::default()
+            }),
+        );
+        let runner = ProcessRunner::new(TwoPerIncrement, InMemoryProcessStore::new());
+        let events: Vec<EventEnvelope<CounterEvent>> = source
+            .read_events(&CounterId("a".into()), Since::BeginningOfStream)
+            .unwrap();
+
+        let err = runner.handle(&events[0], &dispatcher).unwrap_err();
+        assert!(matches!(err, DispatchError::Store(Error::Io(_))));
+        let record = runner
+            .store()
+            .load::<u64, RecordMilestone>("a")
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.state, 1);
+        assert_eq!(
+            record.outbox,
+            [Outgoing {
+                aggregate_id: "a".into(),
+                command: RecordMilestone(12),
+                causation_id: "counter/a/1".into(),
+            }],
+        );
+
+        assert_eq!(
+            runner.handle(&events[0], &dispatcher).unwrap(),
+            Handled::Duplicate,
+        );
+        assert_eq!(reached(&dispatcher), [11, 12]);
+        let record = runner
+            .store()
+            .load::<u64, RecordMilestone>("a")
+            .unwrap()
+            .unwrap();
+        assert!(record.outbox.is_empty());
+    }
+
+    #[test]
+    fn dead_letters_rejected_commands() {
+        let source = counter_events(&[CounterEvent::Incremented; 2]);
+        let dispatcher = Dispatcher::new(
+            MilestonesHandler { reject: Some(11) },
+            Repository::new(InMemoryEventStore::new()),
+        );
+        let runner = ProcessRunner::new(TwoPerIncrement, InMemoryProcessStore::new());
+
+        assert_eq!(runner.catch_up(&source, &dispatcher).unwrap(), 2);
+
+        assert_eq!(reached(&dispatcher), [12, 21, 22]);
+        let record = runner
+            .store()
+            .load::<u64, RecordMilestone>("a")
+            .unwrap()
+            .unwrap();
+        assert!(record.outbox.is_empty());
+        assert_eq!(
+            record.dead_letters,
+            [Outgoing {
+                aggregate_id: "a".into(),
+                command: RecordMilestone(11),
+                causation_id: "counter/a/1".into(),
+            }],
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! Query-side read models built from the events of [`Aggregate`]s.
+
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    envelope::EventEnvelope,
+    store::{Error, EventStore, Since},
+    subscription::{GlobalEventLog, GlobalPosition, Subscription},
+    Aggregate, AggregateEvent, EventNumber,
+};
+
+/// A read model built from the events of all the instances of an [`Aggregate`].
+pub trait Projection {
+    /// The [`Aggregate`] whose events are projected.
+    type Aggregate: Aggregate;
+
+    /// The events being projected.
+    type Event: AggregateEvent<Self::Aggregate>;
+
+    /// Updates the read model with the given event.
+    ///
+    /// Events of the same aggregate are applied in the order they've been appended, while no
+    /// particular order is guaranteed for events of different aggregates.
+    fn apply(&mut self, event: &EventEnvelope<Self::Event>);
+
+    /// Brings the read model back to its initial state, as if no events were applied.
+    fn reset(&mut self);
+}
+
+/// Numbers of the last events applied to a [`Projection`], per each aggregate, along with the
+/// [`GlobalPosition`] the [`GlobalEventLog`] has been read up to.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Checkpoints {
+    streams: HashMap<String, EventNumber>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    position: Option<GlobalPosition>,
+}
+
+impl Checkpoints {
+    /// Returns the number of the last applied event of the aggregate with the given `id`.
+    pub fn get(&self, id: &str) -> Option<EventNumber> {
+        self.streams.get(id).copied()
+    }
+
+    /// Position of the last event read from the [`GlobalEventLog`], if any.
+    pub fn position(&self) -> Option<GlobalPosition> {
+        self.position
+    }
+
+    /// Position to continue reading the stream of the aggregate with the given `id` from.
+    fn since(&self, id: &str) -> Since {
+        self.get(id).map_or(Since::BeginningOfStream, Since::Event)
+    }
+
+    /// Records the given `event` as applied, unless it has been applied already.
+    ///
+    /// Returns `false` if the `event` has been applied already.
+    fn advance<E>(&mut self, event: &EventEnvelope<E>) -> bool {
+        if self.get(&event.aggregate_id) >= Some(event.sequence) {
+            return false;
+        }
+        self.streams
+            .insert(event.aggregate_id.clone(), event.sequence);
+        true
+    }
+}
+
+/// Feeds a [`Projection`] with the events read from an [`EventStore`], keeping track of the
+/// [`Checkpoints`] so no event is applied twice.
+#[derive(Debug)]
+pub struct ProjectionRunner<S, P> {
+    store: S,
+    projection: P,
+    checkpoints: Checkpoints,
+}
+
+impl<S, P> ProjectionRunner<S, P> {
+    /// Creates a new [`ProjectionRunner`] for a [`Projection`] no events have been applied to.
+    pub fn new(store: S, projection: P) -> Self {
+        Self::with_checkpoints(store, projection, Checkpoints::default())
+    }
+
+    /// Creates a new [`ProjectionRunner`] for a [`Projection`] the events up to the given
+    /// [`Checkpoints`] have already been applied to.
+    pub fn with_checkpoints(store: S, projection: P, checkpoints: Checkpoints) -> Self {
+        Self {
+            store,
+            projection,
+            checkpoints,
+        }
+    }
+
+    /// The [`Projection`] being run.
+    pub fn projection(&self) -> &P {
+        &self.projection
+    }
+
+    /// The current [`Checkpoints`] of the [`Projection`], to be persisted along with it.
+    pub fn checkpoints(&self) -> &Checkpoints {
+        &self.checkpoints
+    }
+
+    /// Stops this [`ProjectionRunner`], returning the [`Projection`] and its [`Checkpoints`].
+    pub fn into_inner(self) -> (P, Checkpoints) {
+        (self.projection, self.checkpoints)
+    }
+
+    /// Keeps running the given `catch_up` with the given `interval`, until the `stop` flag is
+    /// raised.
+    fn poll<F>(
+        &mut self,
+        interval: Duration,
+        stop: &AtomicBool,
+        mut catch_up: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&mut Self) -> Result<usize, Error>,
+    {
+        loop {
+            // Checked before catching up, so the events committed before raising the flag
+            // are still applied.
+            let stopped = stop.load(Ordering::Acquire);
+            catch_up(self)?;
+            if stopped {
+                return Ok(());
+            }
+            thread::sleep(interval);
+        }
+    }
+}
+
+impl<S, P> ProjectionRunner<S, P>
+where
+    P: Projection,
+    S: EventStore<P::Aggregate, P::Event>,
+{
+    /// Applies all the events appended since the last [`Checkpoints`].
+    ///
+    /// Lists and reads every stream of the [`Aggregate`], so prefer
+    /// [`ProjectionRunner::catch_up_global()`] for the stores providing a [`GlobalEventLog`].
+    ///
+    /// Returns the number of applied events.
+    pub fn catch_up(&mut self) -> Result<usize, Error> {
+        let mut applied = 0;
+        for id in self.store.stream_ids()? {
+            let since = self.checkpoints.since(&id);
+            for event in self.store.read_events(&id, since)? {
+                self.projection.apply(&event);
+                self.checkpoints.streams.insert(id.clone(), event.sequence);
+                applied += 1;
+            }
+        }
+        Ok(applied)
+    }
+
+    /// Catches up and then keeps applying new events, polling the [`EventStore`] with the
+    /// given `interval`, until the `stop` flag is raised.
+    ///
+    /// All the events committed before raising the `stop` flag are applied before returning.
+    pub fn follow(&mut self, interval: Duration, stop: &AtomicBool) -> Result<(), Error> {
+        self.poll(interval, stop, Self::catch_up)
+    }
+
+    /// Rebuilds the [`Projection`] from scratch, replaying all the events.
+    pub fn rebuild(&mut self) -> Result<usize, Error> {
+        self.projection.reset();
+        self.checkpoints = Checkpoints::default();
+        self.catch_up()
+    }
+}
+
+impl<S, P> ProjectionRunner<S, P>
+where
+    P: Projection,
+    P::Event: DeserializeOwned,
+    S: GlobalEventLog,
+{
+    /// Applies all the events committed to the [`GlobalEventLog`] since the last
+    /// [`Checkpoints::position()`], skipping the events of other [`Aggregate`]s.
+    ///
+    /// Reads only the new events, in commit order, however many streams there are. Events
+    /// already applied by [`ProjectionRunner::catch_up()`] are not applied twice.
+    ///
+    /// Returns the number of applied events.
+    pub fn catch_up_global(&mut self) -> Result<usize, Error> {
+        let mut subscription = match self.checkpoints.position {
+            Some(position) => Subscription::resume(&self.store, position),
+            None => Subscription::new(&self.store),
+        };
+        let aggregate_type = P::Aggregate::aggregate_type();
+        let mut applied = 0;
+        loop {
+            let events = subscription.poll()?;
+            if events.is_empty() {
+                return Ok(applied);
+            }
+            for recorded in events {
+                if recorded.is_of(aggregate_type) {
+                    let event = recorded.decode()?;
+                    if self.checkpoints.advance(&event) {
+                        self.projection.apply(&event);
+                        applied += 1;
+                    }
+                }
+                self.checkpoints.position = Some(recorded.position);
+            }
+        }
+    }
+
+    /// Same as [`ProjectionRunner::follow()`], but catches up via the [`GlobalEventLog`].
+    pub fn follow_global(&mut self, interval: Duration, stop: &AtomicBool) -> Result<(), Error> {
+        self.poll(interval, stop, Self::catch_up_global)
+    }
+}
+
+#[cfg(test)]
+mod projection_runner_spec {
+    use std::{collections::BTreeMap, sync::Arc};
+
+    use crate::{
+        envelope::EventMetadata,
+        mock::{Counter, CounterEvent, CounterId, LightId, Toggled},
+        store::InMemoryEventStore,
+    };
+
+    use super::*;
+
+    /// Total values of all counters, along with the number of applied events.
+    #[derive(Debug, Default)]
+    struct Totals {
+        values: BTreeMap<String, i64>,
+        applied: usize,
+    }
+
+    impl Projection for Totals {
+        type Aggregate = Counter;
+        type Event = CounterEvent;
+
+        fn apply(&mut self, event: &EventEnvelope<CounterEvent>) {
+            let value = self.values.entry(event.aggregate_id.clone()).or_default();
+            match event.event {
+                CounterEvent::Incremented => *value += 1,
+                CounterEvent::Decremented => *value -= 1,
+            }
+            self.applied += 1;
+        }
+
+        fn reset(&mut self) {
+            *self = Self::default();
+        }
+    }
+
+    fn append(store: &InMemoryEventStore, id: &str, events: &[CounterEvent]) {
+        store
+            .append_events(
+                &CounterId(id.into()),
+                events,
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn catches_up_from_checkpoints() {
+        let store = InMemoryEventStore::new();
+        append(&store, "a", &[CounterEvent::Incremented; 2]);
+        append(&store, "b", &[CounterEvent::Decremented]);
+
+        let mut runner = ProjectionRunner::new(&store, Totals::default());
+        assert_eq!(runner.catch_up().unwrap(), 3);
+        assert_eq!(runner.checkpoints().get("a"), EventNumber::new(2));
+        assert_eq!(runner.checkpoints().get("b"), EventNumber::new(1));
+
+        append(&store, "a", &[CounterEvent::Incremented]);
+        assert_eq!(runner.catch_up().unwrap(), 1);
+        assert_eq!(runner.catch_up().unwrap(), 0);
+
+        let (totals, checkpoints) = runner.into_inner();
+        assert_eq!(
+            totals.values,
+            BTreeMap::from([("a".into(), 3), ("b".into(), -1)])
+        );
+        assert_eq!(totals.applied, 4);
+
+        let mut runner = ProjectionRunner::with_checkpoints(&store, totals, checkpoints);
+        append(&store, "b", &[CounterEvent::Decremented]);
+        assert_eq!(runner.catch_up().unwrap(), 1);
+        assert_eq!(runner.projection().values["b"], -2);
+    }
+
+    #[test]
+    fn catches_up_via_global_log() {
+        let store = InMemoryEventStore::new();
+        append(&store, "a", &[CounterEvent::Incremented; 2]);
+        store
+            .append_events(&LightId("l"), &[Toggled], None, &EventMetadata::default())
+            .unwrap();
+        append(&store, "b", &[CounterEvent::Decremented]);
+
+        let mut runner = ProjectionRunner::new(&store, Totals::default());
+        assert_eq!(runner.catch_up_global().unwrap(), 3);
+        assert_eq!(runner.checkpoints().position(), GlobalPosition::new(4));
+        assert_eq!(runner.checkpoints().get("a"), EventNumber::new(2));
+
+        append(&store, "a", &[CounterEvent::Decremented]);
+        assert_eq!(runner.catch_up_global().unwrap(), 1);
+        assert_eq!(runner.catch_up_global().unwrap(), 0);
+        assert_eq!(runner.checkpoints().position(), GlobalPosition::new(5));
+
+        let (totals, checkpoints) = runner.into_inner();
+        assert_eq!(
+            totals.values,
+            BTreeMap::from([("a".into(), 1), ("b".into(), -1)])
+        );
+        let json = serde_json::to_string(&checkpoints).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Checkpoints>(&json).unwrap(),
+            checkpoints
+        );
+    }
+
+    #[test]
+    fn applies_events_once_across_catch_up_modes() {
+        let store = InMemoryEventStore::new();
+        append(&store, "a", &[CounterEvent::Incremented; 2]);
+        let mut runner = ProjectionRunner::new(&store, Totals::default());
+        assert_eq!(runner.catch_up().unwrap(), 2);
+
+        append(&store, "b", &[CounterEvent::Decremented]);
+        assert_eq!(runner.catch_up_global().unwrap(), 1);
+        append(&store, "a", &[CounterEvent::Incremented]);
+        assert_eq!(runner.catch_up().unwrap(), 1);
+        assert_eq!(runner.catch_up_global().unwrap(), 0);
+
+        assert_eq!(runner.projection().applied, 4);
+        assert_eq!(runner.projection().values["a"], 3);
+    }
+
+    #[test]
+    fn rebuilds_from_scratch() {
+        let store = InMemoryEventStore::new();
+        append(&store, "a", &[CounterEvent::Incremented; 3]);
+        let mut runner = ProjectionRunner::new(&store, Totals::default());
+        runner.catch_up().unwrap();
+
+        assert_eq!(runner.rebuild().unwrap(), 3);
+        assert_eq!(runner.projection().values["a"], 3);
+        assert_eq!(runner.projection().applied, 3);
+    }
+
+    #[test]
+    fn follows_new_events() {
+        let store = Arc::new(InMemoryEventStore::new());
+        append(&store, "a", &[CounterEvent::Incremented]);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let follower = thread::spawn({
+            let (store, stop) = (store.clone(), stop.clone());
+            move || {
+                let mut runner = ProjectionRunner::new(&*store, Totals::default());
+                runner.follow(Duration::from_millis(1), &stop).unwrap();
+                runner.into_inner().0
+            }
+        });
+        append(&store, "a", &[CounterEvent::Incremented]);
+        append(&store, "b", &[CounterEvent::Decremented]);
+        stop.store(true, Ordering::Release);
+
+        let totals = follower.join().unwrap();
+        assert_eq!(
+            totals.values,
+            BTreeMap::from([("a".into(), 2), ("b".into(), -1)])
+        );
+    }
+
+    #[test]
+    fn follows_global_log() {
+        let store = Arc::new(InMemoryEventStore::new());
+        append(&store, "a", &[CounterEvent::Incremented]);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let follower = thread::spawn({
+            let (store, stop) = (store.clone(), stop.clone());
+            move || {
+                let mut runner = ProjectionRunner::new(&*store, Totals::default());
+                runner
+                    .follow_global(Duration::from_millis(1), &stop)
+                    .unwrap();
+                runner.into_inner()
+            }
+        });
+        append(&store, "b", &[CounterEvent::Decremented]);
+        stop.store(true, Ordering::Release);
+
+        let (totals, checkpoints) = follower.join().unwrap();
+        assert_eq!(
+            totals.values,
+            BTreeMap::from([("a".into(), 1), ("b".into(), -1)])
+        );
+        assert_eq!(checkpoints.position(), GlobalPosition::new(2));
+    }
+}
@@ -0,0 +1,672 @@
+//! Loading and saving of [`Entity`]s, combining an [`EventStore`] with a [`SnapshotStore`].
+
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{
+    envelope::EventMetadata,
+    snapshot::{NeverSnapshot, NoSnapshots, SnapshotCandidate, SnapshotPolicy, SnapshotStore},
+    store::{Error, EventStore, Since, StreamArchive},
+    Aggregate, AggregateEvent, AggregateId, Entity, EventNumber, HydratedAggregate, Version,
+};
+
+/// A past point to load an aggregate as of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AsOf {
+    /// Right after the event with the given [`Version`] has been applied.
+    Version(Version),
+    /// Right after the last event recorded at or before the given moment has been applied.
+    Timestamp(SystemTime),
+}
+
+/// Loads [`Entity`]s from their latest [`Snapshot`]s and events, and appends new events to
+/// them, taking snapshots according to a [`SnapshotPolicy`].
+///
+/// [`Snapshot`]: crate::snapshot::Snapshot
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Repository<ES, SS = NoSnapshots, P = NeverSnapshot> {
+    events: ES,
+    snapshots: SS,
+    policy: P,
+}
+
+impl<ES> Repository<ES> {
+    /// Creates a new [`Repository`] replaying whole event streams from the given
+    /// [`EventStore`].
+    pub fn new(events: ES) -> Self {
+        Self {
+            events,
+            snapshots: NoSnapshots,
+            policy: NeverSnapshot,
+        }
+    }
+}
+
+impl<ES, SS, P> Repository<ES, SS, P> {
+    /// Makes this [`Repository`] to use the given [`SnapshotStore`], taking snapshots
+    /// according to the given [`SnapshotPolicy`].
+    pub fn with_snapshots<S, Q>(self, snapshots: S, policy: Q) -> Repository<ES, S, Q> {
+        Repository {
+            events: self.events,
+            snapshots,
+            policy,
+        }
+    }
+
+    /// The underlying [`EventStore`].
+    pub fn events(&self) -> &ES {
+        &self.events
+    }
+
+    /// The underlying [`SnapshotStore`].
+    pub fn snapshots(&self) -> &SS {
+        &self.snapshots
+    }
+
+    /// Loads the [`Entity`] with the given `id`.
+    ///
+    /// The aggregate is restored from its latest snapshot (if any), and then only the events
+    /// following that snapshot are replayed. A new snapshot may be taken afterwards, on a
+    /// best-effort basis, see [`SnapshotPolicy::snapshot_failed()`].
+    ///
+    /// Fails with [`Error::Archived`] if the [`Entity`] has been [archived].
+    ///
+    /// [archived]: Repository::archive
+    pub fn load<A, E, I>(&self, id: I) -> Result<Entity<I, A>, Error>
+    where
+        A: Aggregate,
+        E: AggregateEvent<A>,
+        I: AggregateId<A>,
+        ES: EventStore<A, E>,
+        SS: SnapshotStore<A>,
+        P: SnapshotPolicy,
+    {
+        if let Some(tombstone) = self.events.tombstone(&id)? {
+            return Err(Error::Archived(tombstone));
+        }
+        let (mut aggregate, since) = match self.snapshots.load_snapshot(&id)? {
+            Some(s) => (
+                HydratedAggregate::from_snapshot(s.state, s.version),
+                s.version.into(),
+            ),
+            None => (HydratedAggregate::default(), Since::BeginningOfStream),
+        };
+
+        let started = Instant::now();
+        aggregate.apply_events(
+            self.events
+                .read_events(&id, since)?
+                .into_iter()
+                .map(|e| e.event),
+        );
+        let replay_duration = started.elapsed();
+
+        let mut entity = Entity::new(id, aggregate);
+        self.snapshot_if_needed(&mut entity, Some(replay_duration));
+        Ok(entity)
+    }
+
+    /// Restores the aggregate with the given `id` as it was at the given past point, starting
+    /// from its newest snapshot taken before that point (if any).
+    ///
+    /// The returned aggregate's [`version()`] is the version at that point, and archived
+    /// aggregates may be restored too, as long as their events are not compacted.
+    ///
+    /// Fails with [`Error::VersionNotReached`] if the aggregate has never reached the
+    /// requested [`Version`].
+    ///
+    /// [`version()`]: HydratedAggregate::version
+    pub fn load_as_of<A, E, I>(&self, id: &I, as_of: AsOf) -> Result<HydratedAggregate<A>, Error>
+    where
+        A: Aggregate,
+        E: AggregateEvent<A>,
+        I: AggregateId<A>,
+        ES: EventStore<A, E>,
+        SS: SnapshotStore<A>,
+    {
+        let target = match as_of {
+            AsOf::Version(version) => version,
+            AsOf::Timestamp(at) => {
+                let events = self.events.read_events(id, Since::BeginningOfStream)?;
+                let compacted = match events.first() {
+                    Some(first) => first.sequence != EventNumber::MIN_VALUE,
+                    None => self.events.tombstone(id)? > Some(Version::Initial),
+                };
+                if compacted {
+                    return Err(self.compacted(id, Version::Initial)?);
+                }
+                events
+                    .iter()
+                    .take_while(|e| e.timestamp <= at)
+                    .last()
+                    .map_or(Version::Initial, |e| e.sequence.into())
+            }
+        };
+
+        let (mut aggregate, since) = match self.snapshots.load_snapshot_before(id, target)? {
+            Some(s) => (
+                HydratedAggregate::from_snapshot(s.state, s.version),
+                s.version.into(),
+            ),
+            None => (HydratedAggregate::default(), Since::BeginningOfStream),
+        };
+        for event in self.events.read_events(id, since)? {
+            if aggregate.version() == target {
+                break;
+            }
+            if event.sequence != aggregate.version().next_event() {
+                return Err(self.compacted(id, target)?);
+            }
+            aggregate.apply(event.event);
+        }
+
+        if aggregate.version() != target {
+            return Err(self.compacted(id, target)?);
+        }
+        Ok(aggregate)
+    }
+
+    /// Explains why the events of the aggregate with the given `id` up to the `target`
+    /// version are missing.
+    fn compacted<A, E, I>(&self, id: &I, target: Version) -> Result<Error, Error>
+    where
+        A: Aggregate,
+        E: AggregateEvent<A>,
+        I: AggregateId<A>,
+        ES: EventStore<A, E>,
+    {
+        Ok(match self.events.tombstone(id)? {
+            Some(tombstone) if tombstone >= target => Error::Archived(tombstone),
+            _ => Error::VersionNotReached(target),
+        })
+    }
+
+    /// Appends the `events` to the given [`Entity`], both in the [`EventStore`] and in memory,
+    /// attaching the given `metadata` to each of them. A new snapshot may be taken afterwards,
+    /// on a best-effort basis, see [`SnapshotPolicy::snapshot_failed()`].
+    ///
+    /// Fails with [`Error::Conflict`] if the [`Entity`] has been modified concurrently since
+    /// it was loaded.
+    ///
+    /// Returns the new version of the [`Entity`].
+    pub fn append<A, E, I>(
+        &self,
+        entity: &mut Entity<I, A>,
+        events: Vec<E>,
+        metadata: &EventMetadata,
+    ) -> Result<Version, Error>
+    where
+        A: Aggregate,
+        E: AggregateEvent<A>,
+        I: AggregateId<A>,
+        ES: EventStore<A, E>,
+        SS: SnapshotStore<A>,
+        P: SnapshotPolicy,
+    {
+        let expected = entity.aggregate().version();
+        self.events
+            .append_events(entity.id(), &events, Some(expected), metadata)?;
+        entity.aggregate_mut().apply_events(events);
+
+        self.snapshot_if_needed(entity, None);
+        Ok(entity.aggregate().version())
+    }
+
+    /// Archives the given [`Entity`]: takes its final snapshot and marks its stream with a
+    /// tombstone at its current version, so it can be neither loaded nor changed anymore.
+    ///
+    /// If `compact` is set, the archived events are removed as well, but only once the final
+    /// snapshot is confirmed to be readable back from the [`SnapshotStore`].
+    ///
+    /// Fails with [`Error::Conflict`] if the [`Entity`] has been modified concurrently since
+    /// it was loaded.
+    ///
+    /// Returns the tombstone version of the [`Entity`].
+    pub fn archive<A, E, I>(&self, entity: Entity<I, A>, compact: bool) -> Result<Version, Error>
+    where
+        A: Aggregate,
+        E: AggregateEvent<A>,
+        I: AggregateId<A>,
+        ES: EventStore<A, E> + StreamArchive<A>,
+        SS: SnapshotStore<A>,
+    {
+        let (id, aggregate) = (entity.id(), entity.aggregate());
+        let version = aggregate.version();
+        // Snapshot goes first, so the stream is never archived without its final state. A
+        // stale entity only leaves a snapshot of an older version, and is rejected by the
+        // version check of the tombstone.
+        if aggregate.snapshot_version() != Some(version) {
+            self.snapshots
+                .persist_snapshot(id, version, aggregate.state())?;
+        }
+        self.events.archive_stream(id, version)?;
+
+        if compact {
+            let snapshot = self.snapshots.load_snapshot(id)?;
+            if snapshot.is_some_and(|s| s.version == version) {
+                self.events.compact_stream(id, version)?;
+            }
+        }
+        Ok(version)
+    }
+
+    /// Takes a snapshot of the given [`Entity`] if the [`SnapshotPolicy`] demands so,
+    /// reporting a failure to the [`SnapshotPolicy`] instead of returning it.
+    fn snapshot_if_needed<A, I>(&self, entity: &mut Entity<I, A>, replay_duration: Option<Duration>)
+    where
+        A: Aggregate,
+        I: AggregateId<A>,
+        SS: SnapshotStore<A>,
+        P: SnapshotPolicy,
+    {
+        let aggregate = entity.aggregate();
+        let candidate = SnapshotCandidate {
+            version: aggregate.version(),
+            snapshot_version: aggregate.snapshot_version(),
+            replay_duration,
+        };
+        if candidate.events_since_snapshot() == 0 || !self.policy.should_snapshot(&candidate) {
+            return;
+        }
+
+        match self
+            .snapshots
+            .persist_snapshot(entity.id(), candidate.version, aggregate.state())
+        {
+            Ok(()) => entity
+                .aggregate_mut()
+                .set_snapshot_version(candidate.version),
+            Err(e) => self.policy.snapshot_failed(&candidate, &e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod repository_spec {
+    use std::{cell::RefCell, num::NonZeroU64};
+
+    use crate::{
+        mock::{Counter, CounterEvent, CounterId},
+        snapshot::{EveryNEvents, InMemorySnapshotStore, Snapshot},
+        store::InMemoryEventStore,
+    };
+
+    use super::*;
+
+    type Repo = Repository<InMemoryEventStore, InMemorySnapshotStore, EveryNEvents>;
+
+    fn repository(every: u64) -> Repo {
+        Repository::new(InMemoryEventStore::new()).with_snapshots(
+            InMemorySnapshotStore::new(),
+            EveryNEvents(NonZeroU64::new(every).unwrap()),
+        )
+    }
+
+    fn load(repo: &Repo, id: &str) -> Entity<CounterId, Counter> {
+        repo.load::<_, CounterEvent, _>(CounterId(id.into()))
+            .unwrap()
+    }
+
+    /// [`SnapshotStore`] failing to persist any snapshot.
+    struct BrokenSnapshots;
+
+    impl SnapshotStore<Counter> for BrokenSnapshots {
+        fn persist_snapshot<I>(&self, _: &I, _: Version, _: &Counter) -> Result<(), Error>
+        where
+            I: AggregateId<Counter>,
+        {
+            Err(Error::Io(std::io::Error::other("disk full")))
+        }
+
+        fn load_snapshot<I>(&self, _: &I) -> Result<Option<Snapshot<Counter>>, Error>
+        where
+            I: AggregateId<Counter>,
+        {
+            Ok(None)
+        }
+    }
+
+    /// [`SnapshotPolicy`] snapshotting on every event and recording the failed versions.
+    #[derive(Default)]
+    struct RecordFailures(RefCell<Vec<Version>>);
+
+    impl SnapshotPolicy for RecordFailures {
+        fn should_snapshot(&self, _: &SnapshotCandidate) -> bool {
+            true
+        }
+
+        fn snapshot_failed(&self, candidate: &SnapshotCandidate, _: &Error) {
+            self.0.borrow_mut().push(candidate.version);
+        }
+    }
+
+    #[test]
+    fn tolerates_snapshot_failures() {
+        let repo = Repository::new(InMemoryEventStore::new())
+            .with_snapshots(BrokenSnapshots, RecordFailures::default());
+        let mut entity = repo
+            .load::<Counter, CounterEvent, _>(CounterId("a".into()))
+            .unwrap();
+
+        let version = repo
+            .append(
+                &mut entity,
+                vec![CounterEvent::Incremented; 2],
+                &EventMetadata::default(),
+            )
+            .unwrap();
+        assert_eq!(version, Version::new(2));
+        assert_eq!(entity.aggregate().snapshot_version(), None);
+
+        let entity = repo
+            .load::<Counter, CounterEvent, _>(CounterId("a".into()))
+            .unwrap();
+        assert_eq!(entity.aggregate().state().value, 2);
+        assert_eq!(*repo.policy.0.borrow(), [Version::new(2), Version::new(2)],);
+    }
+
+    #[test]
+    fn takes_snapshots_according_to_policy() {
+        let repo = repository(3);
+        let mut entity = load(&repo, "a");
+
+        repo.append(
+            &mut entity,
+            vec![CounterEvent::Incremented; 2],
+            &EventMetadata::default(),
+        )
+        .unwrap();
+        assert_eq!(entity.aggregate().snapshot_version(), None);
+
+        repo.append(
+            &mut entity,
+            vec![CounterEvent::Incremented],
+            &EventMetadata::default(),
+        )
+        .unwrap();
+        assert_eq!(entity.aggregate().snapshot_version(), Some(Version::new(3)));
+
+        let snapshot = SnapshotStore::<Counter>::load_snapshot(repo.snapshots(), entity.id())
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshot.version, Version::new(3));
+        assert_eq!(snapshot.state.value, 3);
+    }
+
+    #[test]
+    fn replays_only_events_after_snapshot() {
+        let repo = repository(100);
+        let mut entity = load(&repo, "a");
+        repo.append(
+            &mut entity,
+            vec![CounterEvent::Incremented; 4],
+            &EventMetadata::default(),
+        )
+        .unwrap();
+
+        // Snapshot deliberately disagreeing with the events, to detect what's replayed.
+        repo.snapshots()
+            .persist_snapshot(entity.id(), Version::new(3), &Counter { value: 100 })
+            .unwrap();
+
+        let entity = load(&repo, "a");
+        assert_eq!(entity.aggregate().state().value, 101);
+        assert_eq!(entity.aggregate().version(), Version::new(4));
+        assert_eq!(entity.aggregate().snapshot_version(), Some(Version::new(3)));
+    }
+
+    #[test]
+    fn archives_entity_with_final_snapshot() {
+        let repo = repository(100);
+        let mut entity = load(&repo, "a");
+        repo.append(
+            &mut entity,
+            vec![CounterEvent::Incremented; 3],
+            &EventMetadata::default(),
+        )
+        .unwrap();
+
+        let tombstone = repo.archive::<_, CounterEvent, _>(entity, false).unwrap();
+        assert_eq!(tombstone, Version::new(3));
+
+        let err = repo
+            .load::<Counter, CounterEvent, _>(CounterId("a".into()))
+            .unwrap_err();
+        assert!(matches!(err, Error::Archived(v) if v == tombstone));
+        let snapshot =
+            SnapshotStore::<Counter>::load_snapshot(repo.snapshots(), &CounterId("a".into()))
+                .unwrap()
+                .unwrap();
+        assert_eq!(snapshot.version, tombstone);
+        assert_eq!(snapshot.state.value, 3);
+
+        let events = EventStore::<Counter, CounterEvent>::read_events(
+            repo.events(),
+            &CounterId("a".into()),
+            Since::BeginningOfStream,
+        )
+        .unwrap();
+        assert_eq!(events.len(), 3);
+    }
+
+    #[test]
+    fn compacts_archived_events() {
+        let repo = repository(100);
+        let mut entity = load(&repo, "a");
+        repo.append(
+            &mut entity,
+            vec![CounterEvent::Incremented; 2],
+            &EventMetadata::default(),
+        )
+        .unwrap();
+        repo.archive::<_, CounterEvent, _>(entity, true).unwrap();
+
+        let events = EventStore::<Counter, CounterEvent>::read_events(
+            repo.events(),
+            &CounterId("a".into()),
+            Since::BeginningOfStream,
+        )
+        .unwrap();
+        assert!(events.is_empty());
+        let err = repo
+            .events()
+            .append_events(
+                &CounterId("a".into()),
+                &[CounterEvent::Incremented],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::Archived(v) if v == Version::new(2)));
+    }
+
+    #[test]
+    fn keeps_events_without_snapshots() {
+        let repo = Repository::new(InMemoryEventStore::new());
+        let id = CounterId("a".into());
+        let mut entity = repo.load::<Counter, CounterEvent, _>(id.clone()).unwrap();
+        repo.append(
+            &mut entity,
+            vec![CounterEvent::Incremented],
+            &EventMetadata::default(),
+        )
+        .unwrap();
+
+        repo.archive::<_, CounterEvent, _>(entity, true).unwrap();
+
+        let events = EventStore::<Counter, CounterEvent>::read_events(
+            repo.events(),
+            &id,
+            Since::BeginningOfStream,
+        )
+        .unwrap();
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn does_not_archive_without_final_snapshot() {
+        let repo = Repository::new(InMemoryEventStore::new())
+            .with_snapshots(BrokenSnapshots, RecordFailures::default());
+        let mut entity = repo
+            .load::<Counter, CounterEvent, _>(CounterId("a".into()))
+            .unwrap();
+        repo.append(
+            &mut entity,
+            vec![CounterEvent::Incremented],
+            &EventMetadata::default(),
+        )
+        .unwrap();
+
+        let err = repo
+            .archive::<_, CounterEvent, _>(entity, true)
+            .unwrap_err();
+        assert!(matches!(err, Error::Io(_)));
+
+        let entity = repo
+            .load::<Counter, CounterEvent, _>(CounterId("a".into()))
+            .unwrap();
+        assert_eq!(entity.aggregate().state().value, 1);
+    }
+
+    #[test]
+    fn rejects_archiving_stale_entity() {
+        let repo = repository(100);
+        let stale = load(&repo, "a");
+        let mut entity = load(&repo, "a");
+        repo.append(
+            &mut entity,
+            vec![CounterEvent::Incremented],
+            &EventMetadata::default(),
+        )
+        .unwrap();
+
+        let err = repo.archive::<_, CounterEvent, _>(stale, true).unwrap_err();
+        assert!(matches!(err, Error::Conflict(c) if c.actual == Version::new(1)));
+        assert_eq!(load(&repo, "a").aggregate().state().value, 1);
+    }
+
+    #[test]
+    fn loads_as_of_version_from_older_snapshot() {
+        let repo = repository(2);
+        let id = CounterId("a".into());
+        let mut entity = load(&repo, "a");
+        for event in [
+            CounterEvent::Incremented,
+            CounterEvent::Incremented,
+            CounterEvent::Decremented,
+            CounterEvent::Incremented,
+            CounterEvent::Incremented,
+        ] {
+            repo.append(&mut entity, vec![event], &EventMetadata::default())
+                .unwrap();
+        }
+        assert_eq!(entity.aggregate().snapshot_version(), Some(Version::new(4)));
+
+        let past = repo
+            .load_as_of::<Counter, CounterEvent, _>(&id, AsOf::Version(Version::new(3)))
+            .unwrap();
+        assert_eq!(past.version(), Version::new(3));
+        assert_eq!(past.snapshot_version(), Some(Version::new(2)));
+        assert_eq!(past.state().value, 1);
+
+        let initial = repo
+            .load_as_of::<Counter, CounterEvent, _>(&id, AsOf::Version(Version::Initial))
+            .unwrap();
+        assert_eq!(initial.version(), Version::Initial);
+        assert_eq!(initial.state().value, 0);
+
+        let err = repo
+            .load_as_of::<Counter, CounterEvent, _>(&id, AsOf::Version(Version::new(6)))
+            .unwrap_err();
+        assert!(matches!(err, Error::VersionNotReached(v) if v == Version::new(6)));
+    }
+
+    #[test]
+    fn loads_as_of_timestamp() {
+        let repo = repository(100);
+        let id = CounterId("a".into());
+        let mut entity = load(&repo, "a");
+        for events in [
+            vec![CounterEvent::Incremented; 2],
+            vec![CounterEvent::Decremented],
+            vec![CounterEvent::Incremented; 3],
+        ] {
+            std::thread::sleep(Duration::from_millis(2));
+            repo.append(&mut entity, events, &EventMetadata::default())
+                .unwrap();
+        }
+        let timestamps = EventStore::<Counter, CounterEvent>::read_events(
+            repo.events(),
+            &id,
+            Since::BeginningOfStream,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|e| e.timestamp)
+        .collect::<Vec<_>>();
+
+        for (at, version, value) in [
+            (timestamps[0] - Duration::from_millis(1), 0, 0),
+            (timestamps[0], 2, 2),
+            (timestamps[2] + Duration::from_micros(1), 3, 1),
+            (timestamps[5] + Duration::from_secs(60), 6, 4),
+        ] {
+            let past = repo
+                .load_as_of::<Counter, CounterEvent, _>(&id, AsOf::Timestamp(at))
+                .unwrap();
+            assert_eq!(past.version(), Version::new(version));
+            assert_eq!(past.state().value, value);
+        }
+    }
+
+    #[test]
+    fn reports_compacted_history() {
+        let repo = repository(100);
+        let id = CounterId("a".into());
+        let mut entity = load(&repo, "a");
+        repo.append(
+            &mut entity,
+            vec![CounterEvent::Incremented; 3],
+            &EventMetadata::default(),
+        )
+        .unwrap();
+        repo.archive::<_, CounterEvent, _>(entity, true).unwrap();
+
+        let archived = repo
+            .load_as_of::<Counter, CounterEvent, _>(&id, AsOf::Version(Version::new(3)))
+            .unwrap();
+        assert_eq!(archived.state().value, 3);
+
+        for as_of in [
+            AsOf::Version(Version::new(2)),
+            AsOf::Timestamp(SystemTime::now()),
+        ] {
+            let err = repo
+                .load_as_of::<Counter, CounterEvent, _>(&id, as_of)
+                .unwrap_err();
+            assert!(matches!(err, Error::Archived(v) if v == Version::new(3)));
+        }
+    }
+
+    #[test]
+    fn rejects_stale_entity() {
+        let repo = repository(100);
+        let mut first = load(&repo, "a");
+        let mut second = load(&repo, "a");
+
+        repo.append(
+            &mut first,
+            vec![CounterEvent::Incremented],
+            &EventMetadata::default(),
+        )
+        .unwrap();
+        let err = repo
+            .append(
+                &mut second,
+                vec![CounterEvent::Decremented],
+                &EventMetadata::default(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::Conflict(c) if c.actual == Version::new(1)));
+        assert_eq!(second.aggregate().version(), Version::Initial);
+    }
+}
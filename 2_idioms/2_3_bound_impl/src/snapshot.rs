@@ -0,0 +1,303 @@
+//! Snapshots of [`Aggregate`]s, allowing to avoid replaying their whole event streams.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    num::NonZeroU64,
+    ops::RangeBounds,
+    sync::RwLock,
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    store::{Error, StreamId},
+    Aggregate, AggregateId, Version,
+};
+
+/// A state of an [`Aggregate`] at some [`Version`].
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Snapshot<A> {
+    /// The version of the aggregate the snapshot was taken at.
+    pub version: Version,
+    /// The state of the aggregate at that version.
+    pub state: A,
+}
+
+/// A place where [`Snapshot`]s of an [`Aggregate`] are kept.
+pub trait SnapshotStore<A>
+where
+    A: Aggregate,
+{
+    /// Stores the `state` of the aggregate with the given `id` taken at the given `version`.
+    fn persist_snapshot<I>(&self, id: &I, version: Version, state: &A) -> Result<(), Error>
+    where
+        I: AggregateId<A>;
+
+    /// Loads the snapshot of the aggregate with the given `id` taken at the latest version,
+    /// if any.
+    fn load_snapshot<I>(&self, id: &I) -> Result<Option<Snapshot<A>>, Error>
+    where
+        I: AggregateId<A>;
+
+    /// Loads the newest snapshot of the aggregate with the given `id` taken at or before the
+    /// given `version`, if any.
+    ///
+    /// By default, only the latest snapshot is considered, so stores keeping the older ones
+    /// should override this.
+    fn load_snapshot_before<I>(
+        &self,
+        id: &I,
+        version: Version,
+    ) -> Result<Option<Snapshot<A>>, Error>
+    where
+        I: AggregateId<A>,
+    {
+        Ok(self.load_snapshot(id)?.filter(|s| s.version <= version))
+    }
+}
+
+/// [`SnapshotStore`] that never stores anything.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoSnapshots;
+
+impl<A> SnapshotStore<A> for NoSnapshots
+where
+    A: Aggregate,
+{
+    fn persist_snapshot<I>(&self, _: &I, _: Version, _: &A) -> Result<(), Error>
+    where
+        I: AggregateId<A>,
+    {
+        Ok(())
+    }
+
+    fn load_snapshot<I>(&self, _: &I) -> Result<Option<Snapshot<A>>, Error>
+    where
+        I: AggregateId<A>,
+    {
+        Ok(None)
+    }
+}
+
+/// [`SnapshotStore`] keeping snapshots in memory.
+///
+/// All the persisted snapshots are kept, so aggregates may be restored as of any of them.
+#[derive(Debug, Default)]
+pub struct InMemorySnapshotStore {
+    snapshots: RwLock<HashMap<StreamId, BTreeMap<Version, serde_json::Value>>>,
+}
+
+impl InMemorySnapshotStore {
+    /// Creates a new empty [`InMemorySnapshotStore`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Finds the newest snapshot of the aggregate with the given `id` within the `versions`.
+    fn find<A, I, R>(&self, id: &I, versions: R) -> Result<Option<Snapshot<A>>, Error>
+    where
+        A: Aggregate + DeserializeOwned,
+        I: AggregateId<A>,
+        R: RangeBounds<Version>,
+    {
+        self.snapshots
+            .read()
+            .unwrap()
+            .get(&StreamId::of(id))
+            .and_then(|s| s.range(versions).next_back())
+            .map(|(&version, state)| {
+                Ok(Snapshot {
+                    version,
+                    state: A::deserialize(state)?,
+                })
+            })
+            .transpose()
+    }
+}
+
+impl<A> SnapshotStore<A> for InMemorySnapshotStore
+where
+    A: Aggregate + Serialize + DeserializeOwned,
+{
+    fn persist_snapshot<I>(&self, id: &I, version: Version, state: &A) -> Result<(), Error>
+    where
+        I: AggregateId<A>,
+    {
+        let state = serde_json::to_value(state)?;
+        self.snapshots
+            .write()
+            .unwrap()
+            .entry(StreamId::of(id))
+            .or_default()
+            .insert(version, state);
+        Ok(())
+    }
+
+    fn load_snapshot<I>(&self, id: &I) -> Result<Option<Snapshot<A>>, Error>
+    where
+        I: AggregateId<A>,
+    {
+        self.find(id, ..)
+    }
+
+    fn load_snapshot_before<I>(
+        &self,
+        id: &I,
+        version: Version,
+    ) -> Result<Option<Snapshot<A>>, Error>
+    where
+        I: AggregateId<A>,
+    {
+        self.find(id, ..=version)
+    }
+}
+
+/// Information about a hydrated aggregate, used to decide whether it's worth taking a new
+/// [`Snapshot`] of it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SnapshotCandidate {
+    /// The current version of the aggregate.
+    pub version: Version,
+    /// The version of the latest snapshot of the aggregate, if any.
+    pub snapshot_version: Option<Version>,
+    /// The time it took to replay the events on top of the latest snapshot, if the aggregate
+    /// has just been loaded.
+    pub replay_duration: Option<Duration>,
+}
+
+impl SnapshotCandidate {
+    /// The number of events to be replayed on top of the latest snapshot to reach the current
+    /// version of the aggregate.
+    ///
+    /// Zero if the snapshot is ahead of the aggregate.
+    pub fn events_since_snapshot(&self) -> u64 {
+        self.version
+            .get()
+            .saturating_sub(self.snapshot_version.map_or(0, Version::get))
+    }
+}
+
+/// A strategy of taking [`Snapshot`]s.
+pub trait SnapshotPolicy {
+    /// Decides whether a new snapshot should be taken for the given aggregate.
+    fn should_snapshot(&self, candidate: &SnapshotCandidate) -> bool;
+
+    /// Reports that taking the snapshot demanded for the given aggregate has failed.
+    ///
+    /// Snapshots are taken on a best-effort basis, so the failure doesn't affect the
+    /// operation the snapshot was taken on, and the snapshot is demanded again on the next
+    /// one. Does nothing by default.
+    fn snapshot_failed(&self, candidate: &SnapshotCandidate, error: &Error) {
+        let _ = (candidate, error);
+    }
+}
+
+/// [`SnapshotPolicy`] never taking any snapshots.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NeverSnapshot;
+
+impl SnapshotPolicy for NeverSnapshot {
+    fn should_snapshot(&self, _: &SnapshotCandidate) -> bool {
+        false
+    }
+}
+
+/// [`SnapshotPolicy`] taking a snapshot once the given number of events has been applied
+/// since the latest one.
+#[derive(Clone, Copy, Debug)]
+pub struct EveryNEvents(pub NonZeroU64);
+
+impl SnapshotPolicy for EveryNEvents {
+    fn should_snapshot(&self, candidate: &SnapshotCandidate) -> bool {
+        candidate.events_since_snapshot() >= self.0.get()
+    }
+}
+
+/// [`SnapshotPolicy`] taking a snapshot once replaying an aggregate becomes too costly.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReplayCostThreshold {
+    /// Maximum number of events allowed to be replayed on top of the latest snapshot.
+    pub max_events: Option<u64>,
+    /// Maximum time allowed to be spent on replaying events on top of the latest snapshot.
+    pub max_duration: Option<Duration>,
+}
+
+impl ReplayCostThreshold {
+    /// Creates a [`ReplayCostThreshold`] limiting the number of replayed events.
+    pub fn events(max: u64) -> Self {
+        Self {
+            max_events: Some(max),
+            max_duration: None,
+        }
+    }
+
+    /// Creates a [`ReplayCostThreshold`] limiting the time spent on replaying events.
+    pub fn duration(max: Duration) -> Self {
+        Self {
+            max_events: None,
+            max_duration: Some(max),
+        }
+    }
+}
+
+impl SnapshotPolicy for ReplayCostThreshold {
+    fn should_snapshot(&self, candidate: &SnapshotCandidate) -> bool {
+        let too_many_events = self
+            .max_events
+            .is_some_and(|max| candidate.events_since_snapshot() > max);
+        let too_long = self
+            .max_duration
+            .zip(candidate.replay_duration)
+            .is_some_and(|(max, took)| took > max);
+        too_many_events || too_long
+    }
+}
+
+#[cfg(test)]
+mod snapshot_policy_spec {
+    use super::*;
+
+    fn candidate(version: u64, snapshot_version: Option<u64>) -> SnapshotCandidate {
+        SnapshotCandidate {
+            version: Version::new(version),
+            snapshot_version: snapshot_version.map(Version::new),
+            replay_duration: None,
+        }
+    }
+
+    #[test]
+    fn every_n_events_counts_from_latest_snapshot() {
+        let policy = EveryNEvents(NonZeroU64::new(3).unwrap());
+
+        for (candidate, expected) in [
+            (candidate(2, None), false),
+            (candidate(3, None), true),
+            (candidate(5, Some(3)), false),
+            (candidate(6, Some(3)), true),
+        ] {
+            assert_eq!(policy.should_snapshot(&candidate), expected);
+        }
+    }
+
+    #[test]
+    fn counts_no_events_since_snapshot_ahead() {
+        assert_eq!(candidate(2, Some(5)).events_since_snapshot(), 0);
+    }
+
+    #[test]
+    fn replay_cost_threshold_checks_events_and_duration() {
+        let policy = ReplayCostThreshold {
+            max_events: Some(10),
+            max_duration: Some(Duration::from_millis(5)),
+        };
+
+        assert!(!policy.should_snapshot(&candidate(10, None)));
+        assert!(policy.should_snapshot(&candidate(11, None)));
+        assert!(!policy.should_snapshot(&candidate(15, Some(5))));
+        assert!(policy.should_snapshot(&SnapshotCandidate {
+            replay_duration: Some(Duration::from_millis(6)),
+            ..candidate(1, None)
+        }));
+    }
+}
@@ -0,0 +1,386 @@
+//! Storage of [`AggregateEvent`]s, keyed by [`AggregateId`].
+
+pub mod file;
+pub mod memory;
+pub mod sqlite;
+
+use std::{fmt, io, time::SystemTime};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    envelope::{EventEnvelope, EventMetadata, RawEventEnvelope},
+    upcast::{UpcastError, Upcasters},
+    Aggregate, AggregateEvent, AggregateId, Entity, Event, EventNumber, HydratedAggregate, Version,
+};
+
+pub use self::{file::FileEventStore, memory::InMemoryEventStore, sqlite::SqliteEventStore};
+
+/// A place where events of an [`Aggregate`] are appended to and read back from.
+///
+/// Events of each aggregate instance form a separate stream, identified by
+/// [`Aggregate::aggregate_type()`] and [`AggregateId::as_str()`].
+pub trait EventStore<A, E>
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+{
+    /// Appends the events to the end of the stream of the aggregate with the given `id`,
+    /// attaching the given `metadata` to each of them.
+    ///
+    /// If the `expected_version` is provided, the events are appended only if the stream is
+    /// still at that version, otherwise [`Error::Conflict`] is returned.
+    ///
+    /// Returns the version of the stream after the events have been appended.
+    fn append_events<I>(
+        &self,
+        id: &I,
+        events: &[E],
+        expected_version: Option<Version>,
+        metadata: &EventMetadata,
+    ) -> Result<Version, Error>
+    where
+        I: AggregateId<A>;
+
+    /// Reads the events of the aggregate with the given `id`, in the order they have been
+    /// appended.
+    fn read_events<I>(&self, id: &I, since: Since) -> Result<Vec<EventEnvelope<E>>, Error>
+    where
+        I: AggregateId<A>;
+
+    /// Lists the [`AggregateId::as_str()`]s of all the aggregates having events in this store,
+    /// in lexicographical order.
+    fn stream_ids(&self) -> Result<Vec<String>, Error>;
+
+    /// Returns the tombstone [`Version`] of the stream of the aggregate with the given `id`,
+    /// if the stream has been archived.
+    ///
+    /// Stores not implementing [`StreamArchive`] never have archived streams.
+    fn tombstone<I>(&self, id: &I) -> Result<Option<Version>, Error>
+    where
+        I: AggregateId<A>,
+    {
+        _ = id;
+        Ok(None)
+    }
+
+    /// Rebuilds the [`Entity`] with the given `id` by replaying all its events.
+    ///
+    /// Fails with [`Error::Archived`] if the stream of the [`Entity`] has been archived.
+    fn load_entity<I>(&self, id: I) -> Result<Entity<I, A>, Error>
+    where
+        I: AggregateId<A>,
+    {
+        if let Some(tombstone) = self.tombstone(&id)? {
+            return Err(Error::Archived(tombstone));
+        }
+        let mut aggregate = HydratedAggregate::default();
+        aggregate.apply_events(
+            self.read_events(&id, Since::BeginningOfStream)?
+                .into_iter()
+                .map(|e| e.event),
+        );
+        Ok(Entity::new(id, aggregate))
+    }
+}
+
+impl<A, E, S> EventStore<A, E> for &S
+where
+    A: Aggregate,
+    E: AggregateEvent<A>,
+    S: EventStore<A, E> + ?Sized,
+{
+    fn append_events<I>(
+        &self,
+        id: &I,
+        events: &[E],
+        expected_version: Option<Version>,
+        metadata: &EventMetadata,
+    ) -> Result<Version, Error>
+    where
+        I: AggregateId<A>,
+    {
+        (**self).append_events(id, events, expected_version, metadata)
+    }
+
+    fn read_events<I>(&self, id: &I, since: Since) -> Result<Vec<EventEnvelope<E>>, Error>
+    where
+        I: AggregateId<A>,
+    {
+        (**self).read_events(id, since)
+    }
+
+    fn stream_ids(&self) -> Result<Vec<String>, Error> {
+        (**self).stream_ids()
+    }
+
+    fn tombstone<I>(&self, id: &I) -> Result<Option<Version>, Error>
+    where
+        I: AggregateId<A>,
+    {
+        (**self).tombstone(id)
+    }
+}
+
+/// A place where streams of finished [`Aggregate`]s may be archived.
+///
+/// An archived stream is marked with a tombstone [`Version`], after which no more events can
+/// be appended to it, and its [`EventStore::tombstone()`] reports that version.
+///
+/// Implemented by [`InMemoryEventStore`] and [`SqliteEventStore`] only: [`FileEventStore`]
+/// is append-only, so it can neither record tombstones nor remove compacted events.
+pub trait StreamArchive<A>
+where
+    A: Aggregate,
+{
+    /// Marks the stream of the aggregate with the given `id` with a tombstone.
+    ///
+    /// Fails with [`Error::Conflict`] if the stream is not at the given `version` anymore.
+    fn archive_stream<I>(&self, id: &I, version: Version) -> Result<(), Error>
+    where
+        I: AggregateId<A>;
+
+    /// Removes the events of the archived stream of the aggregate with the given `id` up to
+    /// (and including) the given `version`, but never past its tombstone.
+    ///
+    /// Does nothing if the stream is not archived. Returns the number of removed events.
+    fn compact_stream<I>(&self, id: &I, version: Version) -> Result<usize, Error>
+    where
+        I: AggregateId<A>;
+}
+
+impl<A, S> StreamArchive<A> for &S
+where
+    A: Aggregate,
+    S: StreamArchive<A> + ?Sized,
+{
+    fn archive_stream<I>(&self, id: &I, version: Version) -> Result<(), Error>
+    where
+        I: AggregateId<A>,
+    {
+        (**self).archive_stream(id, version)
+    }
+
+    fn compact_stream<I>(&self, id: &I, version: Version) -> Result<usize, Error>
+    where
+        I: AggregateId<A>,
+    {
+        (**self).compact_stream(id, version)
+    }
+}
+
+/// A position in an event stream to read events from.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Since {
+    /// Read the whole stream.
+    BeginningOfStream,
+    /// Read only the events following the one with the given number.
+    Event(EventNumber),
+}
+
+impl Since {
+    /// Checks whether the event with the given `number` should be read.
+    #[inline]
+    pub fn includes(self, number: EventNumber) -> bool {
+        match self {
+            Since::BeginningOfStream => true,
+            Since::Event(after) => number > after,
+        }
+    }
+}
+
+impl From<Version> for Since {
+    /// Reads the events following the given [`Version`] of a stream.
+    #[inline]
+    fn from(version: Version) -> Self {
+        match version {
+            Version::Initial => Since::BeginningOfStream,
+            Version::Number(n) => Since::Event(n),
+        }
+    }
+}
+
+/// Stream has been appended to concurrently, so it's not at the expected [`Version`] anymore.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct VersionConflict {
+    /// The version the stream was expected to be at.
+    pub expected: Version,
+    /// The version the stream is actually at.
+    ///
+    /// Reload the aggregate up to this version before retrying.
+    pub actual: Version,
+}
+
+impl VersionConflict {
+    /// Ensures that the `actual` version of a stream is the `expected` one, if any.
+    fn check(expected: Option<Version>, actual: Version) -> Result<(), Self> {
+        match expected {
+            Some(expected) if expected != actual => Err(Self { expected, actual }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for VersionConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected stream at version {}, but it is at {}",
+            self.expected.get(),
+            self.actual.get(),
+        )
+    }
+}
+
+impl std::error::Error for VersionConflict {}
+
+/// An error that may occur while accessing an [`EventStore`] or a
+/// [`SnapshotStore`](crate::snapshot::SnapshotStore).
+#[derive(Debug)]
+pub enum Error {
+    /// Events were not appended because of concurrent modification.
+    Conflict(VersionConflict),
+    /// The stream has been archived with the given tombstone [`Version`], so it can be
+    /// neither loaded nor appended to.
+    ///
+    /// The final state of its aggregate is kept as a
+    /// [`Snapshot`](crate::snapshot::Snapshot) at that version.
+    Archived(Version),
+    /// The stream has never reached the given [`Version`].
+    VersionNotReached(Version),
+    /// Underlying storage failed.
+    Io(io::Error),
+    /// Underlying SQLite database failed.
+    Sqlite(rusqlite::Error),
+    /// An event or a snapshot could not be serialized or deserialized.
+    Serialization(serde_json::Error),
+    /// A persisted event could not be brought to its latest schema.
+    Upcast(UpcastError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Conflict(e) => write!(f, "version conflict: {e}"),
+            Error::Archived(v) => write!(f, "stream is archived at version {}", v.get()),
+            Error::VersionNotReached(v) => {
+                write!(f, "stream has never reached version {}", v.get())
+            }
+            Error::Io(e) => write!(f, "event storage failed: {e}"),
+            Error::Sqlite(e) => write!(f, "event database failed: {e}"),
+            Error::Serialization(e) => write!(f, "serialization failed: {e}"),
+            Error::Upcast(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Conflict(e) => Some(e),
+            Error::Archived(_) | Error::VersionNotReached(_) => None,
+            Error::Io(e) => Some(e),
+            Error::Sqlite(e) => Some(e),
+            Error::Serialization(e) => Some(e),
+            Error::Upcast(e) => Some(e),
+        }
+    }
+}
+
+impl From<UpcastError> for Error {
+    fn from(e: UpcastError) -> Self {
+        Error::Upcast(e)
+    }
+}
+
+impl From<VersionConflict> for Error {
+    fn from(e: VersionConflict) -> Self {
+        Error::Conflict(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Sqlite(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Serialization(e)
+    }
+}
+
+/// Identifier of an event stream.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub(crate) struct StreamId {
+    aggregate_type: String,
+    aggregate_id: String,
+}
+
+impl StreamId {
+    pub(crate) fn of<A, I>(id: &I) -> Self
+    where
+        A: Aggregate,
+        I: AggregateId<A>,
+    {
+        Self {
+            aggregate_type: A::aggregate_type().to_owned(),
+            aggregate_id: id.as_str().to_owned(),
+        }
+    }
+
+    /// Identifier of the stream the given event belongs to.
+    fn of_event<E>(event: &EventEnvelope<E>) -> Self {
+        Self {
+            aggregate_type: event.aggregate_type.clone(),
+            aggregate_id: event.aggregate_id.clone(),
+        }
+    }
+}
+
+/// Brings the given persisted event to its latest schema and deserializes it.
+fn decode<E>(upcasters: &Upcasters, event: RawEventEnvelope) -> Result<EventEnvelope<E>, Error>
+where
+    E: DeserializeOwned,
+{
+    Ok(upcasters.upcast(event)?.decode()?)
+}
+
+/// Wraps the `events` to be appended to the `stream` after its `current` version into
+/// [`RawEventEnvelope`]s, as they're kept by the stores.
+fn encode_all<E>(
+    stream: &StreamId,
+    current: Version,
+    events: &[E],
+    metadata: &EventMetadata,
+) -> Result<Vec<RawEventEnvelope>, Error>
+where
+    E: Event + Serialize,
+{
+    let timestamp = SystemTime::now();
+    let mut version = current;
+    events
+        .iter()
+        .map(|event| {
+            let sequence = version.next_event();
+            version = sequence.into();
+            Ok(EventEnvelope {
+                aggregate_type: stream.aggregate_type.clone(),
+                aggregate_id: stream.aggregate_id.clone(),
+                sequence,
+                event_type: event.event_type().to_owned(),
+                schema_version: event.schema_version(),
+                timestamp,
+                metadata: metadata.clone(),
+                event: serde_json::to_value(event)?,
+            })
+        })
+        .collect()
+}
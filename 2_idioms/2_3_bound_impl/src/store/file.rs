@@ -0,0 +1,565 @@
+//! [`EventStore`] persisting events into an append-only file.
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, BufRead as _, BufReader, Read as _, Seek as _, SeekFrom, Write as _},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    envelope::{EventEnvelope, EventMetadata, RawEventEnvelope},
+    subscription::{GlobalEventLog, GlobalPosition, RecordedEvent},
+    upcast::Upcasters,
+    Aggregate, AggregateEvent, AggregateId, Version,
+};
+
+use super::{decode, encode_all, Error, EventStore, Since, StreamId, VersionConflict};
+
+/// [`EventStore`] persisting events into an append-only file.
+///
+/// Every event is written as a separate JSON line, so the file can be inspected
+/// and processed with usual line-oriented tools.
+///
+/// Byte offsets of the lines are indexed in memory, so reading a stream touches only the
+/// lines of its events instead of rescanning the whole file.
+#[derive(Debug)]
+pub struct FileEventStore {
+    path: PathBuf,
+    inner: Mutex<Inner>,
+    upcasters: Upcasters,
+}
+
+/// Writing side of a [`FileEventStore`], along with the index of the written lines.
+#[derive(Debug)]
+struct Inner {
+    file: File,
+    /// Byte offsets of the lines of every stream, in the order of their sequence numbers.
+    streams: HashMap<StreamId, Vec<u64>>,
+    /// Byte offsets of all the lines, in the order of their global positions.
+    lines: Vec<u64>,
+}
+
+impl FileEventStore {
+    /// Opens the file at the given `path` as an [`FileEventStore`], creating it if it doesn't
+    /// exist yet.
+    ///
+    /// A partially written last line, left by a crash in the middle of appending, is
+    /// truncated.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+        truncate_partial_line(&mut file)?;
+
+        let mut inner = Inner {
+            file,
+            streams: HashMap::new(),
+            lines: Vec::new(),
+        };
+        let mut reader = BufReader::new(File::open(&path)?);
+        let (mut offset, mut line) = (0, String::new());
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)? as u64;
+            if read == 0 {
+                break;
+            }
+            if !line.trim().is_empty() {
+                let event: RawEventEnvelope = serde_json::from_str(&line)?;
+                inner.index(StreamId::of_event(&event), offset);
+            }
+            offset += read;
+        }
+
+        Ok(Self {
+            path,
+            inner: Mutex::new(inner),
+            upcasters: Upcasters::new(),
+        })
+    }
+
+    /// Makes this [`FileEventStore`] to upcast the read events with the given [`Upcasters`].
+    pub fn with_upcasters(mut self, upcasters: Upcasters) -> Self {
+        self.upcasters = upcasters;
+        self
+    }
+
+    /// The path of the file backing this [`FileEventStore`].
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the [`RawEventEnvelope`]s from the lines starting at the given byte `offsets`.
+    ///
+    /// Indexed lines are complete and never rewritten, so they're read without holding the
+    /// writing lock.
+    fn read_lines(&self, offsets: &[u64]) -> Result<Vec<RawEventEnvelope>, Error> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        let (mut pos, mut line) = (0, String::new());
+        offsets
+            .iter()
+            .map(|&offset| {
+                // Relative seeking keeps the buffer, if the line is already in it.
+                reader.seek_relative(offset as i64 - pos as i64)?;
+                line.clear();
+                pos = offset + reader.read_line(&mut line)? as u64;
+                Ok(serde_json::from_str(&line)?)
+            })
+            .collect()
+    }
+}
+
+impl Inner {
+    /// Indexes the line at the given byte `offset` as the next event of the given `stream`.
+    fn index(&mut self, stream: StreamId, offset: u64) {
+        self.streams.entry(stream).or_default().push(offset);
+        self.lines.push(offset);
+    }
+
+    /// Current [`Version`] of the given `stream`.
+    fn version(&self, stream: &StreamId) -> Version {
+        Version::new(self.streams.get(stream).map_or(0, |l| l.len() as u64))
+    }
+}
+
+impl<A, E> EventStore<A, E> for FileEventStore
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + Serialize + DeserializeOwned,
+{
+    fn append_events<I>(
+        &self,
+        id: &I,
+        events: &[E],
+        expected_version: Option<Version>,
+        metadata: &EventMetadata,
+    ) -> Result<Version, Error>
+    where
+        I: AggregateId<A>,
+    {
+        let stream_id = StreamId::of(id);
+        let mut inner = self.inner.lock().unwrap();
+        let current = inner.version(&stream_id);
+        VersionConflict::check(expected_version, current)?;
+
+        let events = encode_all(&stream_id, current, events, metadata)?;
+        let Some(last) = events.last().map(|e| e.sequence) else {
+            return Ok(current);
+        };
+
+        let len = inner.file.metadata()?.len();
+        let (mut buf, mut offsets) = (Vec::new(), Vec::with_capacity(events.len()));
+        for event in &events {
+            offsets.push(len + buf.len() as u64);
+            serde_json::to_writer(&mut buf, event)?;
+            buf.push(b'\n');
+        }
+        if let Err(e) = inner
+            .file
+            .write_all(&buf)
+            .and_then(|()| inner.file.sync_data())
+        {
+            // Rolls back a partially written batch, so it doesn't corrupt the file.
+            _ = inner.file.set_len(len);
+            return Err(e.into());
+        }
+
+        for offset in offsets {
+            inner.index(stream_id.clone(), offset);
+        }
+        Ok(last.into())
+    }
+
+    fn read_events<I>(&self, id: &I, since: Since) -> Result<Vec<EventEnvelope<E>>, Error>
+    where
+        I: AggregateId<A>,
+    {
+        let skip = match since {
+            Since::BeginningOfStream => 0,
+            Since::Event(n) => n.get() as usize,
+        };
+        let offsets = {
+            let inner = self.inner.lock().unwrap();
+            let offsets = inner.streams.get(&StreamId::of(id)).map_or(&[][..], |o| o);
+            offsets.get(skip..).unwrap_or_default().to_vec()
+        };
+        self.read_lines(&offsets)?
+            .into_iter()
+            .map(|event| decode(&self.upcasters, event))
+            .collect()
+    }
+
+    fn stream_ids(&self) -> Result<Vec<String>, Error> {
+        let inner = self.inner.lock().unwrap();
+        let mut ids = inner
+            .streams
+            .keys()
+            .filter(|id| id.aggregate_type == A::aggregate_type())
+            .map(|id| id.aggregate_id.clone())
+            .collect::<Vec<_>>();
+        ids.sort();
+        Ok(ids)
+    }
+}
+
+impl GlobalEventLog for FileEventStore {
+    /// Position of an event is the ordinal number of its line in the file.
+    fn read_global(
+        &self,
+        after: Option<GlobalPosition>,
+        limit: usize,
+    ) -> Result<Vec<RecordedEvent>, Error> {
+        let skip = after.map_or(0, GlobalPosition::get) as usize;
+        let offsets = {
+            let inner = self.inner.lock().unwrap();
+            let offsets = inner.lines.get(skip..).unwrap_or_default();
+            offsets[..limit.min(offsets.len())].to_vec()
+        };
+        self.read_lines(&offsets)?
+            .into_iter()
+            .zip(skip as u64 + 1..)
+            .map(|(event, position)| {
+                Ok(RecordedEvent {
+                    position: GlobalPosition::new(position).unwrap(),
+                    envelope: self.upcasters.upcast(event)?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Truncates the given `file` to its last complete line.
+fn truncate_partial_line(file: &mut File) -> io::Result<()> {
+    let len = file.metadata()?.len();
+    let mut pos = len;
+    let mut byte = [0];
+    while pos > 0 {
+        file.seek(SeekFrom::Start(pos - 1))?;
+        file.read_exact(&mut byte)?;
+        if byte[0] == b'\n' {
+            break;
+        }
+        pos -= 1;
+    }
+    if pos < len {
+        file.set_len(pos)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod file_event_store_spec {
+    use crate::{
+        mock::{Counter, CounterEvent, CounterId},
+        EventNumber,
+    };
+
+    use super::*;
+
+    #[test]
+    fn persists_events_between_openings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let id = CounterId("a".into());
+
+        let store = FileEventStore::open(&path).unwrap();
+        store
+            .append_events(
+                &id,
+                &[CounterEvent::Incremented; 2],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+        drop(store);
+
+        let store = FileEventStore::open(&path).unwrap();
+        let version = store
+            .append_events(
+                &id,
+                &[CounterEvent::Decremented],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+        assert_eq!(version, Version::new(3));
+
+        let entity = EventStore::<Counter, CounterEvent>::load_entity(&store, id).unwrap();
+        assert_eq!(entity.aggregate().state().value, 1);
+        assert_eq!(entity.aggregate().version(), Version::new(3));
+    }
+
+    #[test]
+    fn truncates_partially_written_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let id = CounterId("a".into());
+        let store = FileEventStore::open(&path).unwrap();
+        store
+            .append_events(
+                &id,
+                &[CounterEvent::Incremented],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+        drop(store);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"aggregate_type":"coun"#).unwrap();
+        drop(file);
+
+        let store = FileEventStore::open(&path).unwrap();
+        store
+            .append_events(
+                &id,
+                &[CounterEvent::Incremented],
+                Some(Version::new(1)),
+                &EventMetadata::default(),
+            )
+            .unwrap();
+
+        let entity = EventStore::<Counter, CounterEvent>::load_entity(&store, id).unwrap();
+        assert_eq!(entity.aggregate().state().value, 2);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn writes_event_per_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let store = FileEventStore::open(&path).unwrap();
+        store
+            .append_events(
+                &CounterId("a".into()),
+                &[CounterEvent::Incremented],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+        store
+            .append_events(
+                &CounterId("b".into()),
+                &[CounterEvent::Decremented],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(r#""aggregate_id":"a""#));
+        assert!(lines[1].contains(r#""event_type":"counter_decremented""#));
+    }
+
+    #[test]
+    fn reads_only_requested_stream() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileEventStore::open(dir.path().join("events.jsonl")).unwrap();
+        let (a, b) = (CounterId("a".into()), CounterId("b".into()));
+        store
+            .append_events(
+                &a,
+                &[CounterEvent::Incremented],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+        store
+            .append_events(
+                &b,
+                &[CounterEvent::Decremented; 2],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+        store
+            .append_events(
+                &a,
+                &[CounterEvent::Incremented],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+
+        let events: Vec<EventEnvelope<CounterEvent>> =
+            store.read_events(&b, Since::BeginningOfStream).unwrap();
+        assert_eq!(
+            events.iter().map(|e| e.sequence.get()).collect::<Vec<_>>(),
+            [1, 2],
+        );
+    }
+
+    #[test]
+    fn reads_stream_since_event_via_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let (a, b) = (CounterId("a".into()), CounterId("b".into()));
+        let store = FileEventStore::open(&path).unwrap();
+        for (id, events) in [
+            (&a, &[CounterEvent::Incremented; 2][..]),
+            (&b, &[CounterEvent::Decremented]),
+            (&a, &[CounterEvent::Decremented]),
+        ] {
+            store
+                .append_events(id, events, None, &EventMetadata::default())
+                .unwrap();
+        }
+        drop(store);
+
+        let store = FileEventStore::open(&path).unwrap();
+        store
+            .append_events(
+                &a,
+                &[CounterEvent::Incremented],
+                Some(Version::new(3)),
+                &EventMetadata::default(),
+            )
+            .unwrap();
+
+        let events: Vec<EventEnvelope<CounterEvent>> = store
+            .read_events(&a, Since::Event(EventNumber::new(2).unwrap()))
+            .unwrap();
+        assert_eq!(
+            events
+                .iter()
+                .map(|e| (e.sequence.get(), e.event))
+                .collect::<Vec<_>>(),
+            [
+                (3, CounterEvent::Decremented),
+                (4, CounterEvent::Incremented)
+            ],
+        );
+        let events: Vec<EventEnvelope<CounterEvent>> = store
+            .read_events(&a, Since::Event(EventNumber::new(4).unwrap()))
+            .unwrap();
+        assert!(events.is_empty());
+        assert_eq!(
+            EventStore::<Counter, CounterEvent>::stream_ids(&store).unwrap(),
+            ["a", "b"],
+        );
+    }
+
+    #[test]
+    fn rejects_append_on_version_conflict() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let id = CounterId("a".into());
+        let store = FileEventStore::open(&path).unwrap();
+        store
+            .append_events(
+                &id,
+                &[CounterEvent::Incremented],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+
+        let err = store
+            .append_events(
+                &id,
+                &[CounterEvent::Incremented],
+                Some(Version::Initial),
+                &EventMetadata::default(),
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Conflict(VersionConflict { actual, .. }) if actual == Version::new(1),
+        ));
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+    }
+
+    #[test]
+    fn persists_self_describing_envelopes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let store = FileEventStore::open(&path).unwrap();
+        let metadata = EventMetadata::default()
+            .correlation_id("corr")
+            .with("user", "alice");
+        store
+            .append_events(
+                &CounterId("a".into()),
+                &[CounterEvent::Incremented],
+                None,
+                &metadata,
+            )
+            .unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let envelope = RawEventEnvelope::from_json(contents.trim()).unwrap();
+        assert_eq!(envelope.aggregate_type, "counter");
+        assert_eq!(envelope.event_type, "counter_incremented");
+        assert_eq!(envelope.metadata, metadata);
+
+        let events: Vec<EventEnvelope<CounterEvent>> = store
+            .read_events(&CounterId("a".into()), Since::BeginningOfStream)
+            .unwrap();
+        assert_eq!(events, [envelope.decode().unwrap()]);
+    }
+
+    #[test]
+    fn upcasts_events_of_old_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let old = |sequence, event_type: &str, event| {
+            let mut line = RawEventEnvelope {
+                aggregate_type: "counter".into(),
+                aggregate_id: "a".into(),
+                sequence: EventNumber::new(sequence).unwrap(),
+                event_type: event_type.into(),
+                schema_version: 1,
+                timestamp: std::time::SystemTime::now(),
+                metadata: EventMetadata::default(),
+                event: serde_json::Value::from(event),
+            }
+            .to_json()
+            .unwrap();
+            line.push('\n');
+            line
+        };
+        std::fs::write(
+            &path,
+            [
+                old(1, "counter_incremented", "Inc"),
+                old(2, "counter_incremented", "Inc"),
+                old(3, "counter_decremented", "Dec"),
+            ]
+            .concat(),
+        )
+        .unwrap();
+
+        let store = FileEventStore::open(&path).unwrap().with_upcasters(
+            Upcasters::new()
+                .register("counter_incremented", 1, |_| Ok("Incremented".into()))
+                .register("counter_decremented", 1, |_| Ok("Decremented".into())),
+        );
+        let id = CounterId("a".into());
+        store
+            .append_events(
+                &id,
+                &[CounterEvent::Incremented],
+                Some(Version::new(3)),
+                &EventMetadata::default(),
+            )
+            .unwrap();
+
+        let events: Vec<EventEnvelope<CounterEvent>> =
+            store.read_events(&id, Since::BeginningOfStream).unwrap();
+        assert!(events.iter().all(|e| e.schema_version == 2));
+
+        let entity = EventStore::<Counter, CounterEvent>::load_entity(&store, id).unwrap();
+        assert_eq!(entity.aggregate().state().value, 2);
+        assert_eq!(entity.aggregate().version(), Version::new(4));
+    }
+}
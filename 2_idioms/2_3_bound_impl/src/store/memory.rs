@@ -0,0 +1,403 @@
+//! [`EventStore`] keeping events in memory.
+
+use std::{collections::HashMap, sync::RwLock};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    envelope::{EventEnvelope, EventMetadata, RawEventEnvelope},
+    subscription::{GlobalEventLog, GlobalPosition, RecordedEvent},
+    upcast::Upcasters,
+    Aggregate, AggregateEvent, AggregateId, Version,
+};
+
+use super::{
+    decode, encode_all, Error, EventStore, Since, StreamArchive, StreamId, VersionConflict,
+};
+
+/// [`EventStore`] keeping events in memory.
+///
+/// Events are kept serialized, so a single store may hold streams of different
+/// [`Aggregate`]s, exactly like persistent stores do.
+#[derive(Debug, Default)]
+pub struct InMemoryEventStore {
+    inner: RwLock<Inner>,
+    upcasters: Upcasters,
+}
+
+/// Events of an [`InMemoryEventStore`].
+#[derive(Debug, Default)]
+struct Inner {
+    /// All the events, in the order they've been committed, with the compacted ones removed.
+    log: Vec<Option<RawEventEnvelope>>,
+    /// Streams of the events in the `log`.
+    streams: HashMap<StreamId, Stream>,
+}
+
+/// A single stream of an [`InMemoryEventStore`].
+#[derive(Debug, Default)]
+struct Stream {
+    /// Current version of the stream.
+    version: Version,
+    /// Indices of the events of the stream in the [`Inner::log`].
+    events: Vec<usize>,
+    /// Tombstone of the stream, if it has been archived.
+    tombstone: Option<Version>,
+}
+
+impl InMemoryEventStore {
+    /// Creates a new empty [`InMemoryEventStore`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes this [`InMemoryEventStore`] to upcast the read events with the given
+    /// [`Upcasters`].
+    pub fn with_upcasters(mut self, upcasters: Upcasters) -> Self {
+        self.upcasters = upcasters;
+        self
+    }
+}
+
+impl<A, E> EventStore<A, E> for InMemoryEventStore
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + Serialize + DeserializeOwned,
+{
+    fn append_events<I>(
+        &self,
+        id: &I,
+        events: &[E],
+        expected_version: Option<Version>,
+        metadata: &EventMetadata,
+    ) -> Result<Version, Error>
+    where
+        I: AggregateId<A>,
+    {
+        let stream_id = StreamId::of(id);
+        let mut inner = self.inner.write().unwrap();
+        let Inner { log, streams } = &mut *inner;
+        let (current, tombstone) = streams
+            .get(&stream_id)
+            .map_or((Version::Initial, None), |s| (s.version, s.tombstone));
+        if let Some(tombstone) = tombstone {
+            return Err(Error::Archived(tombstone));
+        }
+
+        VersionConflict::check(expected_version, current)?;
+        let events = encode_all(&stream_id, current, events, metadata)?;
+        if events.is_empty() {
+            return Ok(current);
+        }
+        // Inserted only now, so rejected and empty appends leave no stream behind.
+        let stream = streams.entry(stream_id).or_default();
+        for event in events {
+            stream.version = event.sequence.into();
+            stream.events.push(log.len());
+            log.push(Some(event));
+        }
+        Ok(stream.version)
+    }
+
+    fn read_events<I>(&self, id: &I, since: Since) -> Result<Vec<EventEnvelope<E>>, Error>
+    where
+        I: AggregateId<A>,
+    {
+        let inner = self.inner.read().unwrap();
+        inner
+            .streams
+            .get(&StreamId::of(id))
+            .into_iter()
+            .flat_map(|s| &s.events)
+            .filter_map(|&i| inner.log[i].as_ref())
+            .filter(|e| since.includes(e.sequence))
+            .map(|e| decode(&self.upcasters, e.clone()))
+            .collect()
+    }
+
+    fn stream_ids(&self) -> Result<Vec<String>, Error> {
+        let mut ids = self
+            .inner
+            .read()
+            .unwrap()
+            .streams
+            .iter()
+            .filter(|(id, s)| id.aggregate_type == A::aggregate_type() && !s.events.is_empty())
+            .map(|(id, _)| id.aggregate_id.clone())
+            .collect::<Vec<_>>();
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn tombstone<I>(&self, id: &I) -> Result<Option<Version>, Error>
+    where
+        I: AggregateId<A>,
+    {
+        Ok(self
+            .inner
+            .read()
+            .unwrap()
+            .streams
+            .get(&StreamId::of(id))
+            .and_then(|s| s.tombstone))
+    }
+}
+
+impl<A> StreamArchive<A> for InMemoryEventStore
+where
+    A: Aggregate,
+{
+    fn archive_stream<I>(&self, id: &I, version: Version) -> Result<(), Error>
+    where
+        I: AggregateId<A>,
+    {
+        let stream_id = StreamId::of(id);
+        let mut inner = self.inner.write().unwrap();
+        let (current, tombstone) = inner
+            .streams
+            .get(&stream_id)
+            .map_or((Version::Initial, None), |s| (s.version, s.tombstone));
+        if let Some(tombstone) = tombstone {
+            return Err(Error::Archived(tombstone));
+        }
+        VersionConflict::check(Some(version), current)?;
+        inner.streams.entry(stream_id).or_default().tombstone = Some(version);
+        Ok(())
+    }
+
+    fn compact_stream<I>(&self, id: &I, version: Version) -> Result<usize, Error>
+    where
+        I: AggregateId<A>,
+    {
+        let mut inner = self.inner.write().unwrap();
+        let Inner { log, streams } = &mut *inner;
+        let Some(stream) = streams.get_mut(&StreamId::of(id)) else {
+            return Ok(0);
+        };
+        let Some(tombstone) = stream.tombstone else {
+            return Ok(0);
+        };
+
+        let since = Since::from(version.min(tombstone));
+        let before = stream.events.len();
+        stream.events.retain(|&i| {
+            let keep = log[i].as_ref().is_some_and(|e| since.includes(e.sequence));
+            if !keep {
+                // Leaving a hole, so the positions in the global log stay intact.
+                log[i] = None;
+            }
+            keep
+        });
+        Ok(before - stream.events.len())
+    }
+}
+
+impl GlobalEventLog for InMemoryEventStore {
+    fn read_global(
+        &self,
+        after: Option<GlobalPosition>,
+        limit: usize,
+    ) -> Result<Vec<RecordedEvent>, Error> {
+        let skip = after.map_or(0, GlobalPosition::get);
+        self.inner
+            .read()
+            .unwrap()
+            .log
+            .iter()
+            .zip(1..)
+            .skip(skip as usize)
+            .filter_map(|(event, position)| Some((event.as_ref()?, position)))
+            .take(limit)
+            .map(|(event, position)| {
+                Ok(RecordedEvent {
+                    position: GlobalPosition::new(position).unwrap(),
+                    envelope: self.upcasters.upcast(event.clone())?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod in_memory_event_store_spec {
+    use crate::{
+        mock::{Counter, CounterEvent, CounterId},
+        EventNumber,
+    };
+
+    use super::*;
+
+    #[test]
+    fn appends_and_reads_events_in_order() {
+        let store = InMemoryEventStore::new();
+        let id = CounterId("a".into());
+
+        let version = store
+            .append_events(
+                &id,
+                &[CounterEvent::Incremented, CounterEvent::Incremented],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+        assert_eq!(version, Version::new(2));
+        let version = store
+            .append_events(
+                &id,
+                &[CounterEvent::Decremented],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+        assert_eq!(version, Version::new(3));
+
+        let events: Vec<EventEnvelope<CounterEvent>> =
+            store.read_events(&id, Since::BeginningOfStream).unwrap();
+        assert_eq!(
+            events
+                .iter()
+                .map(|e| (e.sequence.get(), e.event))
+                .collect::<Vec<_>>(),
+            [
+                (1, CounterEvent::Incremented),
+                (2, CounterEvent::Incremented),
+                (3, CounterEvent::Decremented),
+            ],
+        );
+    }
+
+    #[test]
+    fn reads_only_events_since_given_one() {
+        let store = InMemoryEventStore::new();
+        let id = CounterId("a".into());
+        store
+            .append_events(
+                &id,
+                &[CounterEvent::Incremented; 3],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+
+        let events: Vec<EventEnvelope<CounterEvent>> = store
+            .read_events(&id, Since::Event(EventNumber::MIN_VALUE))
+            .unwrap();
+        assert_eq!(
+            events.iter().map(|e| e.sequence.get()).collect::<Vec<_>>(),
+            [2, 3],
+        );
+    }
+
+    #[test]
+    fn keeps_streams_separate() {
+        let store = InMemoryEventStore::new();
+        let (a, b) = (CounterId("a".into()), CounterId("b".into()));
+        store
+            .append_events(
+                &a,
+                &[CounterEvent::Incremented; 2],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+        store
+            .append_events(
+                &b,
+                &[CounterEvent::Decremented],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+
+        let a = EventStore::<Counter, CounterEvent>::load_entity(&store, a).unwrap();
+        let b = EventStore::<Counter, CounterEvent>::load_entity(&store, b).unwrap();
+        assert_eq!(a.aggregate().state().value, 2);
+        assert_eq!(a.aggregate().version(), Version::new(2));
+        assert_eq!(b.aggregate().state().value, -1);
+        assert_eq!(b.aggregate().version(), Version::new(1));
+    }
+
+    #[test]
+    fn loads_unknown_entity_as_initial() {
+        let store = InMemoryEventStore::new();
+
+        let entity =
+            EventStore::<Counter, CounterEvent>::load_entity(&store, CounterId("a".into()))
+                .unwrap();
+        assert_eq!(entity.aggregate().version(), Version::Initial);
+        assert_eq!(entity.aggregate().state().value, 0);
+    }
+
+    #[test]
+    fn rejects_append_on_version_conflict() {
+        let store = InMemoryEventStore::new();
+        let id = CounterId("a".into());
+        store
+            .append_events(
+                &id,
+                &[CounterEvent::Incremented; 2],
+                Some(Version::Initial),
+                &EventMetadata::default(),
+            )
+            .unwrap();
+
+        let err = store
+            .append_events(
+                &id,
+                &[CounterEvent::Decremented],
+                Some(Version::new(1)),
+                &EventMetadata::default(),
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Conflict(VersionConflict { expected, actual })
+                if expected == Version::new(1) && actual == Version::new(2),
+        ));
+
+        let version = store
+            .append_events(
+                &id,
+                &[CounterEvent::Decremented],
+                Some(Version::new(2)),
+                &EventMetadata::default(),
+            )
+            .unwrap();
+        assert_eq!(version, Version::new(3));
+    }
+
+    #[test]
+    fn lists_only_streams_having_events() {
+        let store = InMemoryEventStore::new();
+        let ids = |store: &InMemoryEventStore| {
+            EventStore::<Counter, CounterEvent>::stream_ids(store).unwrap()
+        };
+        store
+            .append_events(
+                &CounterId("a".into()),
+                &[] as &[CounterEvent],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+        store
+            .append_events(
+                &CounterId("b".into()),
+                &[CounterEvent::Incremented],
+                Some(Version::new(1)),
+                &EventMetadata::default(),
+            )
+            .unwrap_err();
+        StreamArchive::<Counter>::archive_stream(&store, &CounterId("c".into()), Version::new(1))
+            .unwrap_err();
+        assert!(ids(&store).is_empty());
+
+        StreamArchive::<Counter>::archive_stream(&store, &CounterId("d".into()), Version::Initial)
+            .unwrap();
+        assert!(ids(&store).is_empty());
+        assert_eq!(
+            EventStore::<Counter, CounterEvent>::tombstone(&store, &CounterId("d".into())).unwrap(),
+            Some(Version::Initial),
+        );
+    }
+}
@@ -0,0 +1,546 @@
+//! [`EventStore`] persisting events into an SQLite database.
+
+use std::{path::Path, sync::Mutex, time::SystemTime};
+
+use rusqlite::{params, Connection, OptionalExtension as _, Row, TransactionBehavior};
+use serde::{
+    de::{DeserializeOwned, Error as _},
+    Serialize,
+};
+
+use crate::{
+    envelope::{EventEnvelope, EventMetadata, RawEventEnvelope},
+    subscription::{GlobalEventLog, GlobalPosition, RecordedEvent},
+    upcast::Upcasters,
+    Aggregate, AggregateEvent, AggregateId, EventNumber, Version,
+};
+
+use super::{
+    decode, encode_all, Error, EventStore, Since, StreamArchive, StreamId, VersionConflict,
+};
+
+/// Schema of the database backing a [`SqliteEventStore`].
+///
+/// Streams are keyed by `aggregate_type` and `aggregate_id`, while the `UNIQUE` constraint
+/// guarantees no two events of a stream share the same number. Archived streams have a row
+/// in the `tombstones` table.
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS events (
+        position       INTEGER PRIMARY KEY AUTOINCREMENT,
+        aggregate_type TEXT    NOT NULL,
+        aggregate_id   TEXT    NOT NULL,
+        sequence       INTEGER NOT NULL CHECK (sequence > 0),
+        event_type     TEXT    NOT NULL,
+        schema_version INTEGER NOT NULL,
+        timestamp      TEXT    NOT NULL,
+        metadata       TEXT    NOT NULL,
+        payload        TEXT    NOT NULL,
+        UNIQUE (aggregate_type, aggregate_id, sequence)
+    );
+    CREATE TABLE IF NOT EXISTS tombstones (
+        aggregate_type TEXT    NOT NULL,
+        aggregate_id   TEXT    NOT NULL,
+        version        INTEGER NOT NULL,
+        PRIMARY KEY (aggregate_type, aggregate_id)
+    );
+";
+
+/// Columns of the `events` table an [`RawEventEnvelope`] is read from.
+const COLUMNS: &str = "aggregate_type, aggregate_id, sequence, event_type, schema_version, \
+                       timestamp, metadata, payload";
+
+/// [`EventStore`] persisting events into an SQLite database.
+///
+/// Events are appended in a single transaction per batch, so either all of them or none are
+/// persisted.
+#[derive(Debug)]
+pub struct SqliteEventStore {
+    conn: Mutex<Connection>,
+    upcasters: Upcasters,
+}
+
+impl SqliteEventStore {
+    /// Opens the database at the given `path` as a [`SqliteEventStore`], creating it if it
+    /// doesn't exist yet.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::new(Connection::open(path)?)
+    }
+
+    /// Creates a new [`SqliteEventStore`] backed by a private in-memory database.
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::new(Connection::open_in_memory()?)
+    }
+
+    /// Creates a new [`SqliteEventStore`] on top of the given `conn`, creating the schema if
+    /// it doesn't exist yet.
+    pub fn new(conn: Connection) -> Result<Self, Error> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            upcasters: Upcasters::new(),
+        })
+    }
+
+    /// Makes this [`SqliteEventStore`] to upcast the read events with the given
+    /// [`Upcasters`].
+    pub fn with_upcasters(mut self, upcasters: Upcasters) -> Self {
+        self.upcasters = upcasters;
+        self
+    }
+}
+
+impl<A, E> EventStore<A, E> for SqliteEventStore
+where
+    A: Aggregate,
+    E: AggregateEvent<A> + Serialize + DeserializeOwned,
+{
+    fn append_events<I>(
+        &self,
+        id: &I,
+        events: &[E],
+        expected_version: Option<Version>,
+        metadata: &EventMetadata,
+    ) -> Result<Version, Error>
+    where
+        I: AggregateId<A>,
+    {
+        let stream_id = StreamId::of(id);
+        let mut conn = self.conn.lock().unwrap();
+        // Taking the write lock upfront, so the version cannot change until commit.
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        if let Some(tombstone) = tombstone(&tx, &stream_id)? {
+            return Err(Error::Archived(tombstone));
+        }
+        let current = version(&tx, &stream_id)?;
+        VersionConflict::check(expected_version, current)?;
+
+        let events = encode_all(&stream_id, current, events, metadata)?;
+        let Some(last) = events.last().map(|e| e.sequence) else {
+            return Ok(current);
+        };
+        {
+            let mut insert = tx.prepare(&format!(
+                "INSERT INTO events ({COLUMNS}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            ))?;
+            for event in &events {
+                insert.execute(params![
+                    event.aggregate_type,
+                    event.aggregate_id,
+                    event.sequence.get(),
+                    event.event_type,
+                    event.schema_version,
+                    humantime::format_rfc3339_nanos(event.timestamp).to_string(),
+                    serde_json::to_string(&event.metadata)?,
+                    serde_json::to_string(&event.event)?,
+                ])?;
+            }
+        }
+        tx.commit()?;
+
+        Ok(last.into())
+    }
+
+    fn read_events<I>(&self, id: &I, since: Since) -> Result<Vec<EventEnvelope<E>>, Error>
+    where
+        I: AggregateId<A>,
+    {
+        let stream_id = StreamId::of(id);
+        let after = match since {
+            Since::BeginningOfStream => 0,
+            Since::Event(n) => n.get(),
+        };
+        let conn = self.conn.lock().unwrap();
+        let mut select = conn.prepare_cached(&format!(
+            "SELECT {COLUMNS} FROM events \
+             WHERE aggregate_type = ?1 AND aggregate_id = ?2 AND sequence > ?3 \
+             ORDER BY sequence",
+        ))?;
+        let rows = select.query_map(
+            params![stream_id.aggregate_type, stream_id.aggregate_id, after],
+            raw_columns,
+        )?;
+        rows.map(|row| decode(&self.upcasters, parse(row?)?))
+            .collect()
+    }
+
+    fn stream_ids(&self) -> Result<Vec<String>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut select = conn.prepare_cached(
+            "SELECT DISTINCT aggregate_id FROM events \
+             WHERE aggregate_type = ?1 ORDER BY aggregate_id",
+        )?;
+        let ids = select
+            .query_map([A::aggregate_type()], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(ids)
+    }
+
+    fn tombstone<I>(&self, id: &I) -> Result<Option<Version>, Error>
+    where
+        I: AggregateId<A>,
+    {
+        tombstone(&self.conn.lock().unwrap(), &StreamId::of(id))
+    }
+}
+
+impl<A> StreamArchive<A> for SqliteEventStore
+where
+    A: Aggregate,
+{
+    fn archive_stream<I>(&self, id: &I, version: Version) -> Result<(), Error>
+    where
+        I: AggregateId<A>,
+    {
+        let stream_id = StreamId::of(id);
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        if let Some(tombstone) = tombstone(&tx, &stream_id)? {
+            return Err(Error::Archived(tombstone));
+        }
+        VersionConflict::check(Some(version), self::version(&tx, &stream_id)?)?;
+        tx.execute(
+            "INSERT INTO tombstones (aggregate_type, aggregate_id, version) VALUES (?1, ?2, ?3)",
+            params![
+                stream_id.aggregate_type,
+                stream_id.aggregate_id,
+                version.get()
+            ],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn compact_stream<I>(&self, id: &I, version: Version) -> Result<usize, Error>
+    where
+        I: AggregateId<A>,
+    {
+        let stream_id = StreamId::of(id);
+        let conn = self.conn.lock().unwrap();
+        let Some(tombstone) = tombstone(&conn, &stream_id)? else {
+            return Ok(0);
+        };
+        let removed = conn.execute(
+            "DELETE FROM events \
+             WHERE aggregate_type = ?1 AND aggregate_id = ?2 AND sequence <= ?3",
+            params![
+                stream_id.aggregate_type,
+                stream_id.aggregate_id,
+                version.min(tombstone).get(),
+            ],
+        )?;
+        Ok(removed)
+    }
+}
+
+impl GlobalEventLog for SqliteEventStore {
+    /// Position of an event is its row ID, which only grows with every commit.
+    fn read_global(
+        &self,
+        after: Option<GlobalPosition>,
+        limit: usize,
+    ) -> Result<Vec<RecordedEvent>, Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut select = conn.prepare_cached(&format!(
+            "SELECT position, {COLUMNS} FROM events WHERE position > ?1 \
+             ORDER BY position LIMIT ?2",
+        ))?;
+        let rows = select.query_map(
+            params![
+                after.map_or(0, GlobalPosition::get),
+                i64::try_from(limit).unwrap_or(i64::MAX),
+            ],
+            |row| Ok((row.get::<_, u64>(0)?, raw_columns_from(row, 1)?)),
+        )?;
+        rows.map(|row| {
+            let (position, columns) = row?;
+            Ok(RecordedEvent {
+                position: GlobalPosition::new(position).expect("positions start at 1"),
+                envelope: self.upcasters.upcast(parse(columns)?)?,
+            })
+        })
+        .collect()
+    }
+}
+
+/// Reads the current [`Version`] of the given stream.
+fn version(conn: &Connection, stream_id: &StreamId) -> Result<Version, Error> {
+    let version = conn
+        .query_row(
+            "SELECT MAX(sequence) FROM events WHERE aggregate_type = ?1 AND aggregate_id = ?2",
+            params![stream_id.aggregate_type, stream_id.aggregate_id],
+            |row| row.get::<_, Option<u64>>(0),
+        )?
+        .map_or(Version::Initial, Version::new);
+    Ok(version)
+}
+
+/// Reads the tombstone [`Version`] of the given stream, if it has been archived.
+fn tombstone(conn: &Connection, stream_id: &StreamId) -> Result<Option<Version>, Error> {
+    let tombstone = conn
+        .query_row(
+            "SELECT version FROM tombstones WHERE aggregate_type = ?1 AND aggregate_id = ?2",
+            params![stream_id.aggregate_type, stream_id.aggregate_id],
+            |row| row.get::<_, u64>(0),
+        )
+        .optional()?
+        .map(Version::new);
+    Ok(tombstone)
+}
+
+/// Raw values of the [`COLUMNS`] of a row.
+type RawColumns = (String, String, u64, String, u32, String, String, String);
+
+/// Reads the [`COLUMNS`] of the given `row`.
+fn raw_columns(row: &Row<'_>) -> rusqlite::Result<RawColumns> {
+    raw_columns_from(row, 0)
+}
+
+/// Reads the [`COLUMNS`] of the given `row`, starting at the given column index.
+fn raw_columns_from(row: &Row<'_>, i: usize) -> rusqlite::Result<RawColumns> {
+    Ok((
+        row.get(i)?,
+        row.get(i + 1)?,
+        row.get(i + 2)?,
+        row.get(i + 3)?,
+        row.get(i + 4)?,
+        row.get(i + 5)?,
+        row.get(i + 6)?,
+        row.get(i + 7)?,
+    ))
+}
+
+/// Assembles a [`RawEventEnvelope`] from the [`RawColumns`] of a row.
+fn parse(columns: RawColumns) -> Result<RawEventEnvelope, Error> {
+    let (
+        aggregate_type,
+        aggregate_id,
+        sequence,
+        event_type,
+        schema_version,
+        timestamp,
+        metadata,
+        payload,
+    ) = columns;
+    Ok(EventEnvelope {
+        aggregate_type,
+        aggregate_id,
+        sequence: EventNumber::new(sequence).expect("checked by the schema"),
+        event_type,
+        schema_version,
+        timestamp: parse_timestamp(&timestamp)?,
+        metadata: serde_json::from_str(&metadata)?,
+        event: serde_json::from_str(&payload)?,
+    })
+}
+
+/// Parses an RFC 3339 `timestamp` of an event.
+fn parse_timestamp(timestamp: &str) -> Result<SystemTime, Error> {
+    humantime::parse_rfc3339(timestamp)
+        .map_err(|e| Error::Serialization(serde_json::Error::custom(e)))
+}
+
+#[cfg(test)]
+mod sqlite_event_store_spec {
+    use crate::mock::{Counter, CounterEvent, CounterId};
+
+    use super::*;
+
+    #[test]
+    fn persists_events_between_openings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.db");
+        let id = CounterId("a".into());
+
+        let store = SqliteEventStore::open(&path).unwrap();
+        store
+            .append_events(
+                &id,
+                &[CounterEvent::Incremented; 2],
+                None,
+                &EventMetadata::default().correlation_id("corr"),
+            )
+            .unwrap();
+        drop(store);
+
+        let store = SqliteEventStore::open(&path).unwrap();
+        let version = store
+            .append_events(
+                &id,
+                &[CounterEvent::Decremented],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+        assert_eq!(version, Version::new(3));
+
+        let events: Vec<EventEnvelope<CounterEvent>> =
+            store.read_events(&id, Since::BeginningOfStream).unwrap();
+        assert_eq!(events[0].metadata.correlation_id.as_deref(), Some("corr"));
+        assert_eq!(events[2].event, CounterEvent::Decremented);
+
+        let entity = EventStore::<Counter, CounterEvent>::load_entity(&store, id).unwrap();
+        assert_eq!(entity.aggregate().state().value, 1);
+        assert_eq!(entity.aggregate().version(), Version::new(3));
+    }
+
+    #[test]
+    fn reads_only_requested_stream_since_given_event() {
+        let store = SqliteEventStore::open_in_memory().unwrap();
+        let (a, b) = (CounterId("a".into()), CounterId("b".into()));
+        for (id, events) in [(&a, 2), (&b, 3), (&a, 1)] {
+            store
+                .append_events(
+                    id,
+                    &vec![CounterEvent::Incremented; events],
+                    None,
+                    &EventMetadata::default(),
+                )
+                .unwrap();
+        }
+
+        let events: Vec<EventEnvelope<CounterEvent>> = store
+            .read_events(&a, Since::Event(EventNumber::MIN_VALUE))
+            .unwrap();
+        assert_eq!(
+            events.iter().map(|e| e.sequence.get()).collect::<Vec<_>>(),
+            [2, 3],
+        );
+        assert_eq!(
+            EventStore::<Counter, CounterEvent>::stream_ids(&store).unwrap(),
+            ["a", "b"],
+        );
+    }
+
+    #[test]
+    fn rejects_whole_batch_on_version_conflict() {
+        let store = SqliteEventStore::open_in_memory().unwrap();
+        let id = CounterId("a".into());
+        store
+            .append_events(
+                &id,
+                &[CounterEvent::Incremented],
+                Some(Version::Initial),
+                &EventMetadata::default(),
+            )
+            .unwrap();
+
+        let err = store
+            .append_events(
+                &id,
+                &[CounterEvent::Incremented; 3],
+                Some(Version::Initial),
+                &EventMetadata::default(),
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Conflict(VersionConflict { actual, .. }) if actual == Version::new(1),
+        ));
+
+        let events: Vec<EventEnvelope<CounterEvent>> =
+            store.read_events(&id, Since::BeginningOfStream).unwrap();
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn enforces_unique_event_numbers_per_stream() {
+        let store = SqliteEventStore::open_in_memory().unwrap();
+        store
+            .append_events(
+                &CounterId("a".into()),
+                &[CounterEvent::Incremented],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap();
+
+        let conn = store.conn.lock().unwrap();
+        let duplicate = conn.execute(
+            &format!(
+                "INSERT INTO events ({COLUMNS}) \
+                 SELECT {COLUMNS} FROM events WHERE aggregate_id = 'a'",
+            ),
+            [],
+        );
+        assert!(duplicate.is_err());
+    }
+
+    #[test]
+    fn archives_and_compacts_stream() {
+        let store = SqliteEventStore::open_in_memory().unwrap();
+        let (a, b) = (CounterId("a".into()), CounterId("b".into()));
+        for id in [&a, &b, &a] {
+            store
+                .append_events(
+                    id,
+                    &[CounterEvent::Incremented],
+                    None,
+                    &EventMetadata::default(),
+                )
+                .unwrap();
+        }
+        assert_eq!(
+            StreamArchive::<Counter>::compact_stream(&store, &a, Version::new(2)).unwrap(),
+            0,
+        );
+
+        StreamArchive::<Counter>::archive_stream(&store, &a, Version::new(2)).unwrap();
+        assert_eq!(
+            EventStore::<Counter, CounterEvent>::tombstone(&store, &a).unwrap(),
+            Some(Version::new(2)),
+        );
+        assert_eq!(
+            StreamArchive::<Counter>::compact_stream(&store, &a, Version::new(2)).unwrap(),
+            2,
+        );
+
+        let err = EventStore::<Counter, CounterEvent>::load_entity(&store, a.clone()).unwrap_err();
+        assert!(matches!(err, Error::Archived(v) if v == Version::new(2)));
+        let err = store
+            .append_events(
+                &a,
+                &[CounterEvent::Incremented],
+                None,
+                &EventMetadata::default(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, Error::Archived(_)));
+
+        let events = store.read_global(None, usize::MAX).unwrap();
+        assert_eq!(
+            events
+                .iter()
+                .map(|e| (e.position.get(), e.envelope.event_id()))
+                .collect::<Vec<_>>(),
+            [(2, "counter/b/1".into())],
+        );
+    }
+
+    #[test]
+    fn exposes_global_log_in_commit_order() {
+        let store = SqliteEventStore::open_in_memory().unwrap();
+        for id in ["b", "a", "b"] {
+            store
+                .append_events(
+                    &CounterId(id.into()),
+                    &[CounterEvent::Incremented],
+                    None,
+                    &EventMetadata::default(),
+                )
+                .unwrap();
+        }
+
+        let events = store
+            .read_global(GlobalPosition::new(1), usize::MAX)
+            .unwrap();
+        assert_eq!(
+            events
+                .iter()
+                .map(|e| (e.position.get(), e.envelope.event_id()))
+                .collect::<Vec<_>>(),
+            [(2, "counter/a/1".into()), (3, "counter/b/2".into())],
+        );
+    }
+}
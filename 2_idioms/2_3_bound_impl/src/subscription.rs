@@ -0,0 +1,305 @@
+//! Store-wide log of events of all [`Aggregate`]s, in the order they've been committed.
+//!
+//! [`Aggregate`]: crate::Aggregate
+
+use std::{
+    num::{NonZeroU64, NonZeroUsize},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    envelope::{EventEnvelope, RawEventEnvelope},
+    store::Error,
+};
+
+/// Position of an event in the global log of an event store, starting at 1.
+///
+/// Unlike an [`EventNumber`], it's unique across all the streams of the store.
+///
+/// [`EventNumber`]: crate::EventNumber
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct GlobalPosition(NonZeroU64);
+
+impl GlobalPosition {
+    /// The position of the first event ever committed.
+    pub const MIN_VALUE: GlobalPosition = GlobalPosition(NonZeroU64::new(1).unwrap());
+
+    /// Creates a new [`GlobalPosition`] from a number.
+    ///
+    /// Returns `None` if the number is `0`.
+    #[inline]
+    pub fn new(position: u64) -> Option<Self> {
+        NonZeroU64::new(position).map(GlobalPosition)
+    }
+
+    /// The raw value of the position.
+    #[inline]
+    pub fn get(self) -> u64 {
+        self.0.get()
+    }
+}
+
+/// An event read from the global log, along with its [`GlobalPosition`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedEvent {
+    /// Position of the event in the global log.
+    pub position: GlobalPosition,
+    /// The event itself, brought to its latest schema.
+    pub envelope: RawEventEnvelope,
+}
+
+impl RecordedEvent {
+    /// Checks whether the event belongs to an aggregate of the given type.
+    pub fn is_of(&self, aggregate_type: &str) -> bool {
+        self.envelope.aggregate_type == aggregate_type
+    }
+
+    /// Deserializes the payload of the event.
+    pub fn decode<E>(&self) -> Result<EventEnvelope<E>, serde_json::Error>
+    where
+        E: DeserializeOwned,
+    {
+        self.envelope.clone().decode()
+    }
+}
+
+/// An event store exposing the events of all its streams as a single log, ordered by commit.
+pub trait GlobalEventLog {
+    /// Reads at most `limit` events committed after the given position, or from the very
+    /// beginning if it's `None`.
+    fn read_global(
+        &self,
+        after: Option<GlobalPosition>,
+        limit: usize,
+    ) -> Result<Vec<RecordedEvent>, Error>;
+}
+
+impl<L> GlobalEventLog for &L
+where
+    L: GlobalEventLog + ?Sized,
+{
+    fn read_global(
+        &self,
+        after: Option<GlobalPosition>,
+        limit: usize,
+    ) -> Result<Vec<RecordedEvent>, Error> {
+        (**self).read_global(after, limit)
+    }
+}
+
+/// Subscription to a [`GlobalEventLog`], delivering every committed event exactly once and
+/// in commit order.
+///
+/// Consumers persist the [`Subscription::position()`] once they've processed the delivered
+/// events, and [`Subscription::resume()`] from it later.
+#[derive(Debug)]
+pub struct Subscription<L> {
+    log: L,
+    position: Option<GlobalPosition>,
+    batch_size: NonZeroUsize,
+}
+
+impl<L> Subscription<L> {
+    /// Default maximum number of events delivered by a single [`Subscription::poll()`].
+    pub const DEFAULT_BATCH_SIZE: NonZeroUsize = NonZeroUsize::new(256).unwrap();
+
+    /// Subscribes to the given `log` from its very beginning.
+    pub fn new(log: L) -> Self {
+        Self {
+            log,
+            position: None,
+            batch_size: Self::DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// Subscribes to the given `log`, delivering only the events committed after the given
+    /// `position`.
+    pub fn resume(log: L, position: GlobalPosition) -> Self {
+        Self {
+            position: Some(position),
+            ..Self::new(log)
+        }
+    }
+
+    /// Limits the number of events delivered by a single [`Subscription::poll()`].
+    pub fn with_batch_size(mut self, batch_size: NonZeroUsize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Position of the last delivered event, if any.
+    pub fn position(&self) -> Option<GlobalPosition> {
+        self.position
+    }
+}
+
+impl<L> Subscription<L>
+where
+    L: GlobalEventLog,
+{
+    /// Delivers the next batch of committed events, which is empty if there are no new ones.
+    pub fn poll(&mut self) -> Result<Vec<RecordedEvent>, Error> {
+        let events = self.log.read_global(self.position, self.batch_size.get())?;
+        if let Some(last) = events.last() {
+            self.position = Some(last.position);
+        }
+        Ok(events)
+    }
+
+    /// Keeps delivering new events to the `consumer`, polling the [`GlobalEventLog`] with the
+    /// given `interval`, until the `stop` flag is raised.
+    pub fn follow<F>(
+        &mut self,
+        interval: Duration,
+        stop: &AtomicBool,
+        mut consumer: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(RecordedEvent),
+    {
+        loop {
+            // Checked before polling, so the events committed before raising the flag are
+            // still delivered.
+            let stopped = stop.load(Ordering::Acquire);
+            let events = self.poll()?;
+            let caught_up = events.len() < self.batch_size.get();
+            events.into_iter().for_each(&mut consumer);
+            if caught_up {
+                if stopped {
+                    return Ok(());
+                }
+                thread::sleep(interval);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod subscription_spec {
+    use std::sync::Arc;
+
+    use crate::{
+        envelope::EventMetadata,
+        mock::{Counter, CounterEvent, CounterId, Light, LightId, Toggled},
+        store::{EventStore, FileEventStore, InMemoryEventStore},
+        Aggregate as _,
+    };
+
+    use super::*;
+
+    /// Appends interleaved events of different aggregates to the `store`.
+    fn interleave<S>(store: &S)
+    where
+        S: EventStore<Counter, CounterEvent> + EventStore<Light, Toggled>,
+    {
+        let metadata = EventMetadata::default();
+        let counter = |id: &str, events: &[CounterEvent]| {
+            store
+                .append_events(&CounterId(id.into()), events, None, &metadata)
+                .unwrap();
+        };
+        counter("a", &[CounterEvent::Incremented; 2]);
+        store
+            .append_events(&LightId("l"), &[Toggled], None, &metadata)
+            .unwrap();
+        counter("b", &[CounterEvent::Decremented]);
+        counter("a", &[CounterEvent::Decremented]);
+    }
+
+    fn ids(events: &[RecordedEvent]) -> Vec<(u64, String)> {
+        events
+            .iter()
+            .map(|e| (e.position.get(), e.envelope.event_id()))
+            .collect()
+    }
+
+    fn delivers_all_events_in_commit_order<L: GlobalEventLog>(log: L) {
+        let mut subscription = Subscription::new(log);
+        let events = subscription.poll().unwrap();
+
+        assert_eq!(
+            ids(&events),
+            [
+                (1, "counter/a/1".into()),
+                (2, "counter/a/2".into()),
+                (3, "light/l/1".into()),
+                (4, "counter/b/1".into()),
+                (5, "counter/a/3".into()),
+            ],
+        );
+        assert_eq!(subscription.position(), GlobalPosition::new(5));
+        assert!(subscription.poll().unwrap().is_empty());
+
+        assert!(events[2].is_of(Light::aggregate_type()));
+        assert_eq!(events[2].decode::<Toggled>().unwrap().event, Toggled);
+    }
+
+    #[test]
+    fn delivers_all_events_of_memory_store_in_commit_order() {
+        let store = InMemoryEventStore::new();
+        interleave(&store);
+        delivers_all_events_in_commit_order(&store);
+    }
+
+    #[test]
+    fn delivers_all_events_of_file_store_in_commit_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileEventStore::open(dir.path().join("events.jsonl")).unwrap();
+        interleave(&store);
+        delivers_all_events_in_commit_order(&store);
+    }
+
+    #[test]
+    fn resumes_from_saved_position() {
+        let store = InMemoryEventStore::new();
+        interleave(&store);
+        let mut subscription =
+            Subscription::new(&store).with_batch_size(NonZeroUsize::new(2).unwrap());
+        assert_eq!(subscription.poll().unwrap().len(), 2);
+        let saved = subscription.position().unwrap();
+
+        let mut subscription = Subscription::resume(&store, saved);
+        assert_eq!(
+            ids(&subscription.poll().unwrap()),
+            [
+                (3, "light/l/1".into()),
+                (4, "counter/b/1".into()),
+                (5, "counter/a/3".into()),
+            ],
+        );
+    }
+
+    #[test]
+    fn follows_new_events() {
+        let store = Arc::new(InMemoryEventStore::new());
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let follower = thread::spawn({
+            let (store, stop) = (store.clone(), stop.clone());
+            move || {
+                let mut delivered = Vec::new();
+                Subscription::new(&*store)
+                    .with_batch_size(NonZeroUsize::MIN)
+                    .follow(Duration::from_millis(1), &stop, |e| delivered.push(e))
+                    .unwrap();
+                delivered
+            }
+        });
+        interleave(&*store);
+        stop.store(true, Ordering::Release);
+
+        let delivered = follower.join().unwrap();
+        assert_eq!(
+            delivered
+                .iter()
+                .map(|e| e.position.get())
+                .collect::<Vec<_>>(),
+            [1, 2, 3, 4, 5],
+        );
+    }
+}
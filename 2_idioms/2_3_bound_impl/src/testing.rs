@@ -0,0 +1,268 @@
+//! Fixtures for testing [`Aggregate`]s in a given-when-then style.
+//!
+//! ```ignore
+//! AggregateFixture::new(CounterHandler)
+//!     .given([CounterEvent::Incremented])
+//!     .when(CounterCommand::Decrement)
+//!     .then_expect_events([CounterEvent::Decremented]);
+//! ```
+
+use std::fmt::{self, Write as _};
+
+use crate::{
+    command::{Command, CommandHandler},
+    Aggregate, AggregateEvent, HydratedAggregate,
+};
+
+/// Test fixture executing [`Command`]s on an [`Aggregate`] built from prior events.
+#[derive(Debug)]
+pub struct AggregateFixture<A, H>
+where
+    A: Aggregate,
+{
+    handler: H,
+    aggregate: HydratedAggregate<A>,
+}
+
+impl<A, H> AggregateFixture<A, H>
+where
+    A: Aggregate,
+{
+    /// Creates a new [`AggregateFixture`] handling [`Command`]s with the given
+    /// [`CommandHandler`], starting from the initial state of the [`Aggregate`].
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            aggregate: HydratedAggregate::default(),
+        }
+    }
+
+    /// Applies the given prior `events` to the [`Aggregate`].
+    pub fn given<E, I>(mut self, events: I) -> Self
+    where
+        E: AggregateEvent<A>,
+        I: IntoIterator<Item = E>,
+    {
+        self.aggregate.apply_events(events);
+        self
+    }
+
+    /// The [`Aggregate`] the [`Command`] will be executed on.
+    pub fn aggregate(&self) -> &HydratedAggregate<A> {
+        &self.aggregate
+    }
+
+    /// Executes the `command` on the [`Aggregate`].
+    pub fn when<C>(self, command: C) -> Outcome<A, C>
+    where
+        C: Command<A>,
+        H: CommandHandler<A, C>,
+    {
+        let result = self.handler.handle(self.aggregate.state(), command);
+        Outcome {
+            aggregate: self.aggregate,
+            result,
+        }
+    }
+}
+
+/// Outcome of executing a [`Command`] in an [`AggregateFixture`], to make assertions on.
+pub struct Outcome<A, C>
+where
+    A: Aggregate,
+    C: Command<A>,
+{
+    aggregate: HydratedAggregate<A>,
+    result: Result<Vec<C::Event>, C::Error>,
+}
+
+impl<A, C> Outcome<A, C>
+where
+    A: Aggregate,
+    C: Command<A>,
+{
+    /// The events emitted by the [`Command`], or the reason of its rejection.
+    pub fn result(&self) -> &Result<Vec<C::Event>, C::Error> {
+        &self.result
+    }
+}
+
+impl<A, C> Outcome<A, C>
+where
+    A: Aggregate,
+    C: Command<A>,
+    C::Event: fmt::Debug + PartialEq,
+    C::Error: fmt::Debug + PartialEq,
+{
+    /// Asserts that the [`Command`] emitted exactly the `expected` events, in order.
+    ///
+    /// Returns the [`Aggregate`] with the emitted events applied, for further assertions on
+    /// its state.
+    ///
+    /// # Panics
+    ///
+    /// With a diff of the expected and actual events, if they differ.
+    #[track_caller]
+    pub fn then_expect_events<I>(self, expected: I) -> HydratedAggregate<A>
+    where
+        I: IntoIterator<Item = C::Event>,
+    {
+        let expected = expected.into_iter().collect::<Vec<_>>();
+        let actual = match self.result {
+            Ok(actual) => actual,
+            Err(e) => panic!(
+                "expected events, but command was rejected with {e:?}\n{}",
+                diff(&expected, &[]),
+            ),
+        };
+        if actual != expected {
+            panic!(
+                "unexpected events (- expected, + actual):\n{}",
+                diff(&expected, &actual),
+            );
+        }
+
+        let mut aggregate = self.aggregate;
+        aggregate.apply_events(actual);
+        aggregate
+    }
+
+    /// Asserts that the [`Command`] has been rejected with the `expected` error.
+    ///
+    /// # Panics
+    ///
+    /// If the [`Command`] emitted events or has been rejected with another error.
+    #[track_caller]
+    pub fn then_expect_error(self, expected: C::Error) {
+        match self.result {
+            Ok(actual) => panic!(
+                "expected command to be rejected with {expected:?}, but it emitted events\n{}",
+                diff(&[], &actual),
+            ),
+            Err(actual) if actual != expected => {
+                panic!("unexpected error (- expected, + actual):\n- {expected:?}\n+ {actual:?}")
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+impl<A, C> fmt::Debug for Outcome<A, C>
+where
+    A: Aggregate + fmt::Debug,
+    C: Command<A>,
+    C::Event: fmt::Debug,
+    C::Error: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Outcome")
+            .field("aggregate", &self.aggregate)
+            .field("result", &self.result)
+            .finish()
+    }
+}
+
+/// Renders a line-per-item diff of the `expected` and `actual` sequences, marking the
+/// missing items with `-`, and the unexpected ones with `+`.
+fn diff<T>(expected: &[T], actual: &[T]) -> String
+where
+    T: fmt::Debug + PartialEq,
+{
+    // Lengths of the longest common subsequences of the suffixes.
+    let mut lcs = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            _ = writeln!(out, "  {:?}", expected[i]);
+            (i, j) = (i + 1, j + 1);
+        } else if j == actual.len() || (i < expected.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            _ = writeln!(out, "- {:?}", expected[i]);
+            i += 1;
+        } else {
+            _ = writeln!(out, "+ {:?}", actual[j]);
+            j += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod aggregate_fixture_spec {
+    use crate::{
+        mock::{CounterCommand, CounterError, CounterEvent, CounterHandler},
+        Version,
+    };
+
+    use super::*;
+
+    #[test]
+    fn expects_emitted_events() {
+        let counter = AggregateFixture::new(CounterHandler)
+            .given([CounterEvent::Incremented; 2])
+            .when(CounterCommand::Decrement)
+            .then_expect_events([CounterEvent::Decremented]);
+
+        assert_eq!(counter.state().value, 1);
+        assert_eq!(counter.version(), Version::new(3));
+    }
+
+    #[test]
+    fn expects_rejection() {
+        AggregateFixture::new(CounterHandler)
+            .given([CounterEvent::Incremented, CounterEvent::Decremented])
+            .when(CounterCommand::Decrement)
+            .then_expect_error(CounterError::BelowZero);
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected events (- expected, + actual):\n\
+                               - Decremented\n\
+                               + Incremented\n")]
+    fn reports_unexpected_events() {
+        AggregateFixture::new(CounterHandler)
+            .when(CounterCommand::Increment)
+            .then_expect_events([CounterEvent::Decremented]);
+    }
+
+    #[test]
+    #[should_panic(expected = "command was rejected with BelowZero\n- Decremented\n")]
+    fn reports_unexpected_rejection() {
+        AggregateFixture::new(CounterHandler)
+            .when(CounterCommand::Decrement)
+            .then_expect_events([CounterEvent::Decremented]);
+    }
+
+    #[test]
+    #[should_panic(expected = "but it emitted events\n+ Incremented\n")]
+    fn reports_missing_rejection() {
+        AggregateFixture::new(CounterHandler)
+            .when(CounterCommand::Increment)
+            .then_expect_error(CounterError::BelowZero);
+    }
+
+    #[test]
+    fn diffs_sequences_line_by_line() {
+        use CounterEvent::{Decremented as D, Incremented as I};
+
+        assert_eq!(
+            diff(&[I, D, I, I], &[I, I, D, I]),
+            "  Incremented\n\
+             - Decremented\n  \
+             Incremented\n\
+             + Decremented\n  \
+             Incremented\n",
+        );
+        assert_eq!(diff::<CounterEvent>(&[], &[]), "");
+    }
+}
@@ -0,0 +1,192 @@
+//! Upcasting of events persisted with outdated schemas.
+
+use std::{collections::HashMap, fmt};
+
+use crate::envelope::RawEventEnvelope;
+
+/// Transformation of a serialized event payload from one schema version into the next one.
+pub type Upcaster = dyn Fn(serde_json::Value) -> Result<serde_json::Value, String> + Send + Sync;
+
+/// Registry of [`Upcaster`]s, keyed by [`Event::event_type()`] and schema version.
+///
+/// Upcasters are chained, so an event persisted with any old schema version is brought to
+/// the latest one before being deserialized.
+///
+/// [`Event::event_type()`]: crate::Event::event_type
+#[derive(Default)]
+pub struct Upcasters {
+    upcasters: HashMap<(String, u32), Box<Upcaster>>,
+}
+
+impl Upcasters {
+    /// Creates a new empty [`Upcasters`] registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the `upcaster` transforming payloads of the events of the given `event_type`
+    /// from the `from_version` schema into the `from_version + 1` one.
+    ///
+    /// Replaces the previously registered upcaster for the same event type and version, if any.
+    pub fn register<F>(
+        mut self,
+        event_type: impl Into<String>,
+        from_version: u32,
+        upcaster: F,
+    ) -> Self
+    where
+        F: Fn(serde_json::Value) -> Result<serde_json::Value, String> + Send + Sync + 'static,
+    {
+        self.upcasters
+            .insert((event_type.into(), from_version), Box::new(upcaster));
+        self
+    }
+
+    /// Brings the given event to the latest schema version known to this registry.
+    pub fn upcast(&self, mut event: RawEventEnvelope) -> Result<RawEventEnvelope, UpcastError> {
+        let mut key = (event.event_type.clone(), event.schema_version);
+        while let Some(upcaster) = self.upcasters.get(&key) {
+            event.event = upcaster(event.event).map_err(|reason| UpcastError {
+                event_type: key.0.clone(),
+                schema_version: key.1,
+                reason,
+            })?;
+            event.schema_version += 1;
+            key.1 = event.schema_version;
+        }
+        Ok(event)
+    }
+}
+
+impl fmt::Debug for Upcasters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.upcasters.keys()).finish()
+    }
+}
+
+/// An [`Upcaster`] failed to transform an event payload.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UpcastError {
+    /// Type of the event that failed to be upcasted.
+    pub event_type: String,
+    /// Schema version the event failed to be upcasted from.
+    pub schema_version: u32,
+    /// Why the upcasting failed.
+    pub reason: String,
+}
+
+impl fmt::Display for UpcastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot upcast `{}` event from schema version {}: {}",
+            self.event_type, self.schema_version, self.reason,
+        )
+    }
+}
+
+impl std::error::Error for UpcastError {}
+
+#[cfg(test)]
+mod upcasters_spec {
+    use std::time::SystemTime;
+
+    use serde_json::json;
+
+    use crate::{envelope::EventMetadata, EventNumber};
+
+    use super::*;
+
+    fn raw(event_type: &str, schema_version: u32, event: serde_json::Value) -> RawEventEnvelope {
+        RawEventEnvelope {
+            aggregate_type: "user".into(),
+            aggregate_id: "1".into(),
+            sequence: EventNumber::MIN_VALUE,
+            event_type: event_type.into(),
+            schema_version,
+            timestamp: SystemTime::UNIX_EPOCH,
+            metadata: EventMetadata::default(),
+            event,
+        }
+    }
+
+    fn upcasters() -> Upcasters {
+        Upcasters::new()
+            // v1 -> v2: `name` split into `first_name` and `last_name`.
+            .register("user_registered", 1, |mut v| {
+                let name = v["name"].take();
+                let name = name.as_str().ok_or("missing `name`")?;
+                let (first, last) = name.split_once(' ').unwrap_or((name, ""));
+                Ok(json!({ "first_name": first, "last_name": last }))
+            })
+            // v2 -> v3: `email` added.
+            .register("user_registered", 2, |mut v| {
+                v["email"] = json!(null);
+                Ok(v)
+            })
+            // v3 -> v4: `last_name` renamed to `surname`.
+            .register("user_registered", 3, |mut v| {
+                let last_name = v["last_name"].take();
+                let v = v.as_object_mut().ok_or("not an object")?;
+                v.remove("last_name");
+                v.insert("surname".into(), last_name);
+                Ok(json!(v))
+            })
+    }
+
+    #[test]
+    fn chains_upcasters_up_to_latest_version() {
+        let event = upcasters()
+            .upcast(raw("user_registered", 1, json!({ "name": "John Doe" })))
+            .unwrap();
+
+        assert_eq!(event.schema_version, 4);
+        assert_eq!(
+            event.event,
+            json!({ "first_name": "John", "surname": "Doe", "email": null }),
+        );
+    }
+
+    #[test]
+    fn starts_chain_from_persisted_version() {
+        let event = upcasters()
+            .upcast(raw(
+                "user_registered",
+                3,
+                json!({ "first_name": "Jane", "last_name": "Roe", "email": "jane@example.com" }),
+            ))
+            .unwrap();
+
+        assert_eq!(event.schema_version, 4);
+        assert_eq!(
+            event.event,
+            json!({ "first_name": "Jane", "surname": "Roe", "email": "jane@example.com" }),
+        );
+    }
+
+    #[test]
+    fn leaves_other_events_intact() {
+        for event in [
+            raw("user_deleted", 1, json!({ "at": 1 })),
+            raw("user_registered", 4, json!({ "first_name": "Jane" })),
+        ] {
+            assert_eq!(upcasters().upcast(event.clone()).unwrap(), event);
+        }
+    }
+
+    #[test]
+    fn reports_failed_step() {
+        let err = upcasters()
+            .upcast(raw("user_registered", 1, json!({})))
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            UpcastError {
+                event_type: "user_registered".into(),
+                schema_version: 1,
+                reason: "missing `name`".into(),
+            },
+        );
+    }
+}
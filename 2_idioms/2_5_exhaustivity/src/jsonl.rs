@@ -0,0 +1,251 @@
+//! [JSON Lines] logs of [`user::Event`]s.
+//!
+//! [JSON Lines]: https://jsonlines.org
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    io::{self, BufRead, Write},
+};
+
+use crate::user::{self, FromEventsError, User};
+
+/// Writer of [`user::Event`]s, one JSON object per line.
+#[derive(Debug)]
+pub struct EventWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> EventWriter<W> {
+    /// Creates a new [`EventWriter`] on top of the given `writer`.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Writes the `event` as a single line.
+    pub fn write(&mut self, event: &user::Event) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, event)?;
+        self.writer.write_all(b"\n")
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reader of [`user::Event`]s written by an [`EventWriter`], skipping blank lines.
+#[derive(Debug)]
+pub struct EventReader<R> {
+    lines: io::Lines<R>,
+    line: usize,
+}
+
+impl<R: BufRead> EventReader<R> {
+    /// Creates a new [`EventReader`] on top of the given `reader`.
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            line: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for EventReader<R> {
+    type Item = Result<user::Event, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = self.lines.next()?;
+            self.line += 1;
+            let line = match line {
+                Ok(l) if l.trim().is_empty() => continue,
+                Ok(l) => l,
+                Err(e) => return Some(Err(ReadError::Io(e))),
+            };
+            return Some(serde_json::from_str(&line).map_err(|e| ReadError::Json {
+                line: self.line,
+                error: e,
+            }));
+        }
+    }
+}
+
+/// Reason of an [`EventReader`] failing.
+#[derive(Debug)]
+pub enum ReadError {
+    /// Underlying reader failed.
+    Io(io::Error),
+    /// The given line doesn't contain a valid [`user::Event`].
+    Json {
+        /// Number of the line, starting from 1.
+        line: usize,
+        /// Why the line is invalid.
+        error: serde_json::Error,
+    },
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Io(e) => write!(f, "cannot read events: {e}"),
+            ReadError::Json { line, error } => write!(f, "invalid event on line {line}: {error}"),
+        }
+    }
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReadError::Io(e) => Some(e),
+            ReadError::Json { error, .. } => Some(error),
+        }
+    }
+}
+
+/// Replays the log read from the given `reader` into [`User`]s, in the order of their first
+/// appearance in the log.
+pub fn replay<R: BufRead>(reader: R) -> Result<Vec<User>, ReplayError> {
+    let mut order = Vec::new();
+    let mut streams = HashMap::<user::Id, Vec<user::Event>>::new();
+    for event in EventReader::new(reader) {
+        let event = event.map_err(ReplayError::Read)?;
+        let id = event.user_id();
+        streams
+            .entry(id)
+            .or_insert_with(|| {
+                order.push(id);
+                vec![]
+            })
+            .push(event);
+    }
+
+    order
+        .into_iter()
+        .map(|id| User::from_events(&streams[&id]).map_err(|error| ReplayError::User { id, error }))
+        .collect()
+}
+
+/// Reason of [`replay()`] failing.
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The log cannot be read.
+    Read(ReadError),
+    /// Events of the [`User`] with the given `id` cannot be replayed.
+    User {
+        /// ID of the failed [`User`].
+        id: user::Id,
+        /// Why the [`User`] cannot be replayed.
+        error: FromEventsError,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Read(e) => write!(f, "{e}"),
+            ReplayError::User { id, error } => write!(f, "cannot replay user {}: {error}", id.0),
+        }
+    }
+}
+
+impl Error for ReplayError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReplayError::Read(e) => Some(e),
+            ReplayError::User { error, .. } => Some(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod jsonl_spec {
+    use std::time::{Duration, SystemTime};
+
+    use crate::event;
+
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn events() -> Vec<user::Event> {
+        vec![
+            user::Event::Created(event::UserCreated {
+                user_id: user::Id(1),
+                at: user::CreationDateTime(at(1)),
+            }),
+            user::Event::Created(event::UserCreated {
+                user_id: user::Id(2),
+                at: user::CreationDateTime(at(2)),
+            }),
+            user::Event::NameUpdated(event::UserNameUpdated {
+                user_id: user::Id(1),
+                name: Some(user::Name("Alice".into())),
+                at: at(3),
+            }),
+            user::Event::Deleted(event::UserDeleted {
+                user_id: user::Id(2),
+                at: user::DeletionDateTime(at(4)),
+            }),
+        ]
+    }
+
+    #[test]
+    fn encodes_tagged_json_with_rfc3339_timestamps() {
+        let json = serde_json::to_value(&events()[2]).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "name_updated",
+                "user_id": 1,
+                "name": "Alice",
+                "at": "1970-01-01T00:00:03.000000000Z",
+            }),
+        );
+    }
+
+    #[test]
+    fn reads_back_written_events() {
+        let mut writer = EventWriter::new(Vec::new());
+        for event in &events() {
+            writer.write(event).unwrap();
+        }
+        let log = writer.into_inner().unwrap();
+        assert_eq!(log.iter().filter(|&&b| b == b'\n').count(), 4);
+
+        let read = EventReader::new(&log[..])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(read, events());
+    }
+
+    #[test]
+    fn replays_log_into_users() {
+        let mut writer = EventWriter::new(Vec::new());
+        for event in &events() {
+            writer.write(event).unwrap();
+        }
+        let log = writer.into_inner().unwrap();
+
+        let users = replay(&log[..]).unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].name, Some(user::Name("Alice".into())));
+        assert_eq!(users[1].deleted_at, Some(user::DeletionDateTime(at(4))));
+    }
+
+    #[test]
+    fn reports_invalid_lines() {
+        let log = b"\n{\"type\":\"unknown\"}\n";
+
+        let err = replay(&log[..]).unwrap_err();
+        assert!(matches!(
+            err,
+            ReplayError::Read(ReadError::Json { line: 2, .. })
+        ));
+    }
+}
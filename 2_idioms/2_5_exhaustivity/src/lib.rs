@@ -1,11 +1,103 @@
+pub mod jsonl;
+pub mod presence;
+pub mod users;
+
 pub trait EventSourced<Ev: ?Sized> {
     fn apply(&mut self, event: &Ev);
 }
 
+/// [`EventSourced`] checking whether an event may be applied at all, before applying it.
+pub trait TryEventSourced<Ev: ?Sized> {
+    /// Reason of an event being inapplicable.
+    type Error;
+
+    /// Applies the `event`, unless it violates invariants, leaving `self` untouched then.
+    fn try_apply(&mut self, event: &Ev) -> Result<(), Self::Error>;
+}
+
+/// Defines an enum of events, along with routing of each of its variants to the
+/// [`EventSourced`] implementation of the wrapped event.
+///
+/// Every variant must wrap exactly one event. The routing is generated as an exhaustive
+/// `match`, so no variant can be left unhandled: a type can only apply the enum if it's able
+/// to apply each of the wrapped events. The types listed after `for` get the
+/// [`EventSourced`] implementation for the enum, while any other type may implement it by
+/// calling the generated `apply_to()` method.
+///
+/// ```
+/// # use task_2_5::{event_enum, EventSourced};
+/// struct Opened;
+/// struct Closed;
+///
+/// #[derive(Default)]
+/// struct Door {
+///     open: bool,
+/// }
+///
+/// event_enum! {
+///     pub enum DoorEvent for Door {
+///         Opened(Opened),
+///         Closed(Closed),
+///     }
+/// }
+///
+/// impl EventSourced<Opened> for Door {
+///     fn apply(&mut self, _: &Opened) {
+///         self.open = true;
+///     }
+/// }
+///
+/// impl EventSourced<Closed> for Door {
+///     fn apply(&mut self, _: &Closed) {
+///         self.open = false;
+///     }
+/// }
+///
+/// let mut door = Door::default();
+/// door.apply(&DoorEvent::Opened(Opened));
+/// assert!(door.open);
+/// ```
+#[macro_export]
+macro_rules! event_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident $(for $($target:ty),+)? {
+            $($(#[$variant_meta:meta])* $variant:ident($event:ty)),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $($(#[$variant_meta])* $variant($event),)+
+        }
+
+        impl $name {
+            /// Applies the wrapped event to the given `target`.
+            $vis fn apply_to<T>(&self, target: &mut T)
+            where
+                $(T: $crate::EventSourced<$event>,)+
+            {
+                match self {
+                    $($name::$variant(ev) => <T as $crate::EventSourced<$event>>::apply(target, ev),)+
+                }
+            }
+        }
+
+        $($(
+            impl $crate::EventSourced<$name> for $target {
+                fn apply(&mut self, event: &$name) {
+                    event.apply_to(self);
+                }
+            }
+        )+)?
+    };
+}
+
 pub mod user {
-    use std::time::SystemTime;
+    use std::{borrow::Borrow, error::Error, fmt, time::SystemTime};
+
+    use serde::{Deserialize, Serialize};
 
-    use super::{event, EventSourced};
+    use super::{event, rfc3339, EventSourced, TryEventSourced};
 
     #[derive(Debug)]
     pub struct User {
@@ -17,6 +109,47 @@ pub mod user {
         pub deleted_at: Option<DeletionDateTime>,
     }
 
+    impl User {
+        /// Creates a new [`User`] out of its [`event::UserCreated`].
+        pub fn new(ev: &event::UserCreated) -> Self {
+            Self {
+                id: ev.user_id,
+                name: None,
+                online_since: None,
+                created_at: ev.at,
+                last_activity_at: LastActivityDateTime(ev.at.0),
+                deleted_at: None,
+            }
+        }
+
+        /// Restores a [`User`] from its `events`, the first of which must be
+        /// [`Event::Created`].
+        ///
+        /// Stops at the first [`Event`] that cannot be applied, reporting its index.
+        pub fn from_events<I>(events: I) -> Result<Self, FromEventsError>
+        where
+            I: IntoIterator,
+            I::Item: Borrow<Event>,
+        {
+            let mut events = events.into_iter();
+            let mut user = match events.next() {
+                Some(ev) => match ev.borrow() {
+                    Event::Created(ev) => Self::new(ev),
+                    _ => return Err(FromEventsError::NotCreated),
+                },
+                None => return Err(FromEventsError::Empty),
+            };
+            for (index, ev) in events.enumerate() {
+                user.try_apply(ev.borrow())
+                    .map_err(|error| FromEventsError::Apply {
+                        index: index + 1,
+                        error,
+                    })?;
+            }
+            Ok(user)
+        }
+    }
+
     impl EventSourced<event::UserCreated> for User {
         fn apply(&mut self, ev: &event::UserCreated) {
             self.id = ev.user_id;
@@ -51,87 +184,461 @@ pub mod user {
         }
     }
 
-    #[derive(Debug)]
-    pub enum Event {
-        Created(event::UserCreated),
-        NameUpdated(event::UserNameUpdated),
-        Online(event::UserBecameOnline),
-        Offline(event::UserBecameOffline),
-        Deleted(event::UserDeleted),
-    }
-
-    impl EventSourced<Event> for User {
-        fn apply(&mut self, ev: &Event) {
-            // Creation
-            if let Event::Created(ev) = ev {
-                self.apply(ev);
-                return;
+    crate::event_enum! {
+        /// Any event happening to a [`User`], serialized as JSON tagged with its `type`.
+        #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+        #[serde(tag = "type", rename_all = "snake_case")]
+        pub enum Event for User {
+            Created(event::UserCreated),
+            NameUpdated(event::UserNameUpdated),
+            Online(event::UserBecameOnline),
+            Offline(event::UserBecameOffline),
+            Deleted(event::UserDeleted),
+        }
+    }
+
+    impl Event {
+        /// ID of the [`User`] this [`Event`] happened to.
+        pub fn user_id(&self) -> Id {
+            match self {
+                Event::Created(ev) => ev.user_id,
+                Event::NameUpdated(ev) => ev.user_id,
+                Event::Online(ev) => ev.user_id,
+                Event::Offline(ev) => ev.user_id,
+                Event::Deleted(ev) => ev.user_id,
             }
-            // Online/Offline
-            if let Event::Online(ev) = ev {
-                self.apply(ev);
-                return;
+        }
+    }
+
+    impl TryEventSourced<Event> for User {
+        type Error = ApplyError;
+
+        fn try_apply(&mut self, ev: &Event) -> Result<(), ApplyError> {
+            let user_id = ev.user_id();
+            if user_id != self.id {
+                return Err(ApplyError::IdMismatch {
+                    expected: self.id,
+                    actual: user_id,
+                });
+            }
+            if let Some(at) = self.deleted_at {
+                return Err(ApplyError::Deleted(at));
+            }
+            if let Event::Created(_) = ev {
+                return Err(ApplyError::AlreadyCreated(self.created_at));
+            }
+            self.apply(ev);
+            Ok(())
+        }
+    }
+
+    /// Reason of an [`Event`] being inapplicable to a [`User`].
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ApplyError {
+        /// [`User`] has been created already at the given moment.
+        AlreadyCreated(CreationDateTime),
+        /// [`User`] has been deleted at the given moment, so nothing may happen to it anymore.
+        Deleted(DeletionDateTime),
+        /// [`Event`] happened to another [`User`].
+        IdMismatch {
+            /// ID of the [`User`] the [`Event`] is applied to.
+            expected: Id,
+            /// ID of the [`User`] the [`Event`] happened to.
+            actual: Id,
+        },
+    }
+
+    impl fmt::Display for ApplyError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                ApplyError::AlreadyCreated(_) => write!(f, "user is already created"),
+                ApplyError::Deleted(_) => write!(f, "user is deleted"),
+                ApplyError::IdMismatch { expected, actual } => write!(
+                    f,
+                    "event of user {} cannot be applied to user {}",
+                    actual.0, expected.0,
+                ),
             }
-            if let Event::Offline(ev) = ev {
-                self.apply(ev);
-                return;
+        }
+    }
+
+    impl Error for ApplyError {}
+
+    /// Reason of [`User::from_events()`] failing.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum FromEventsError {
+        /// No [`Event`]s were provided.
+        Empty,
+        /// The first [`Event`] is not [`Event::Created`].
+        NotCreated,
+        /// The [`Event`] at the given `index` cannot be applied.
+        Apply {
+            /// Index of the failed [`Event`].
+            index: usize,
+            /// Why the [`Event`] cannot be applied.
+            error: ApplyError,
+        },
+    }
+
+    impl fmt::Display for FromEventsError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                FromEventsError::Empty => write!(f, "no events to restore user from"),
+                FromEventsError::NotCreated => {
+                    write!(f, "events of user must start with its creation")
+                }
+                FromEventsError::Apply { index, error } => {
+                    write!(f, "cannot apply event #{index}: {error}")
+                }
             }
-            // Deletion
-            if let Event::Deleted(ev) = ev {
-                self.apply(ev);
+        }
+    }
+
+    impl Error for FromEventsError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            match self {
+                FromEventsError::Empty | FromEventsError::NotCreated => None,
+                FromEventsError::Apply { error, .. } => Some(error),
             }
         }
     }
 
-    #[derive(Clone, Copy, Debug)]
+    #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
+    #[serde(transparent)]
     pub struct Id(pub u64);
 
-    #[derive(Clone, Debug)]
+    #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+    #[serde(transparent)]
     pub struct Name(pub Box<str>);
 
-    #[derive(Clone, Copy, Debug)]
-    pub struct CreationDateTime(pub SystemTime);
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+    pub struct CreationDateTime(#[serde(with = "rfc3339")] pub SystemTime);
 
-    #[derive(Clone, Copy, Debug)]
-    pub struct LastActivityDateTime(pub SystemTime);
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+    pub struct LastActivityDateTime(#[serde(with = "rfc3339")] pub SystemTime);
 
-    #[derive(Clone, Copy, Debug)]
-    pub struct DeletionDateTime(pub SystemTime);
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+    pub struct DeletionDateTime(#[serde(with = "rfc3339")] pub SystemTime);
 }
 
 pub mod event {
     use std::time::SystemTime;
 
-    use super::user;
+    use serde::{Deserialize, Serialize};
 
-    #[derive(Debug)]
+    use super::{rfc3339, user};
+
+    #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
     pub struct UserCreated {
         pub user_id: user::Id,
         pub at: user::CreationDateTime,
     }
 
-    #[derive(Debug)]
+    #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
     pub struct UserNameUpdated {
         pub user_id: user::Id,
         pub name: Option<user::Name>,
+        #[serde(with = "rfc3339")]
         pub at: SystemTime,
     }
 
-    #[derive(Debug)]
+    #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
     pub struct UserBecameOnline {
         pub user_id: user::Id,
+        #[serde(with = "rfc3339")]
         pub at: SystemTime,
     }
 
-    #[derive(Debug)]
+    #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
     pub struct UserBecameOffline {
         pub user_id: user::Id,
+        #[serde(with = "rfc3339")]
         pub at: SystemTime,
     }
 
-    #[derive(Debug)]
+    #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
     pub struct UserDeleted {
         pub user_id: user::Id,
         pub at: user::DeletionDateTime,
     }
 }
+
+/// (De)serialization of [`SystemTime`](std::time::SystemTime) as an RFC 3339 string.
+///
+/// Always serializes in UTC, while accepting any UTC offset on deserialization.
+mod rfc3339 {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use serde::{de::Error as _, ser::Error as _, Deserialize as _, Deserializer, Serializer};
+
+    /// Last second representable in RFC 3339 (`9999-12-31T23:59:59Z`) since the Unix epoch.
+    const MAX_SECS: u64 = 253_402_300_799;
+
+    pub fn serialize<S: Serializer>(time: &SystemTime, ser: S) -> Result<S::Ok, S::Error> {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(since) if since.as_secs() <= MAX_SECS => {
+                ser.collect_str(&humantime::format_rfc3339_nanos(*time))
+            }
+            Ok(_) => Err(S::Error::custom(
+                "time after year 9999 is not representable",
+            )),
+            Err(_) => Err(S::Error::custom(
+                "time before the Unix epoch is not supported",
+            )),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<SystemTime, D::Error> {
+        parse(&String::deserialize(de)?).map_err(D::Error::custom)
+    }
+
+    /// Parses the given RFC 3339 timestamp with an arbitrary UTC offset.
+    fn parse(s: &str) -> Result<SystemTime, String> {
+        let s = s.to_ascii_uppercase();
+        let Some((local, sign, offset)) = split_offset(&s) else {
+            return humantime::parse_rfc3339(&s).map_err(|e| e.to_string());
+        };
+        let offset = parse_offset(offset).ok_or_else(|| format!("invalid UTC offset in `{s}`"))?;
+        let local = humantime::parse_rfc3339(&format!("{local}Z")).map_err(|e| e.to_string())?;
+        // Local time is ahead of UTC for positive offsets.
+        match sign {
+            b'+' => local.checked_sub(offset),
+            _ => local.checked_add(offset),
+        }
+        .filter(|t| t.duration_since(UNIX_EPOCH).is_ok())
+        .ok_or_else(|| format!("`{s}` is out of the supported range"))
+    }
+
+    /// Splits the given timestamp into its local part, the sign and the `HH:MM` of its
+    /// numeric UTC offset, if it has one.
+    fn split_offset(s: &str) -> Option<(&str, u8, &str)> {
+        let at = s.len().checked_sub(6)?;
+        let sign = *s.as_bytes().get(at)?;
+        (sign == b'+' || sign == b'-').then(|| (&s[..at], sign, &s[at + 1..]))
+    }
+
+    /// Parses the given `HH:MM` UTC offset.
+    fn parse_offset(hh_mm: &str) -> Option<Duration> {
+        let (hh, mm) = hh_mm.split_once(':')?;
+        if hh.len() != 2 || mm.len() != 2 {
+            return None;
+        }
+        let (hh, mm) = (hh.parse::<u64>().ok()?, mm.parse::<u64>().ok()?);
+        (hh < 24 && mm < 60).then(|| Duration::from_secs(hh * 3600 + mm * 60))
+    }
+
+    #[cfg(test)]
+    mod rfc3339_spec {
+        use serde_json::{json, Value};
+
+        use super::*;
+
+        fn to_json(time: SystemTime) -> Result<Value, serde_json::Error> {
+            serialize(&time, serde_json::value::Serializer)
+        }
+
+        fn from_json(s: &str) -> Result<SystemTime, serde_json::Error> {
+            deserialize(json!(s))
+        }
+
+        #[test]
+        fn round_trips_in_utc() {
+            let time = UNIX_EPOCH + Duration::new(1_700_000_000, 123);
+
+            let json = to_json(time).unwrap();
+
+            assert_eq!(json, json!("2023-11-14T22:13:20.000000123Z"));
+            assert_eq!(from_json(json.as_str().unwrap()).unwrap(), time);
+        }
+
+        #[test]
+        fn accepts_utc_offsets() {
+            let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+            for s in [
+                "2023-11-14T22:13:20Z",
+                "2023-11-14t22:13:20z",
+                "2023-11-14T22:13:20+00:00",
+                "2023-11-14T22:13:20-00:00",
+                "2023-11-15T00:13:20+02:00",
+                "2023-11-14T17:43:20-04:30",
+            ] {
+                assert_eq!(from_json(s).unwrap(), time, "{s}");
+            }
+            assert_eq!(
+                to_json(from_json("2023-11-15T00:13:20+02:00").unwrap()).unwrap(),
+                json!("2023-11-14T22:13:20.000000000Z"),
+            );
+        }
+
+        #[test]
+        fn rejects_malformed_offsets() {
+            for s in [
+                "2023-11-14T22:13:20",
+                "2023-11-14T22:13:20+2:00",
+                "2023-11-14T22:13:20+24:00",
+                "2023-11-14T22:13:20+02:60",
+                "1970-01-01T00:00:00+00:01",
+            ] {
+                assert!(from_json(s).is_err(), "{s}");
+            }
+        }
+
+        #[test]
+        fn refuses_unrepresentable_times() {
+            assert!(to_json(UNIX_EPOCH - Duration::from_secs(1)).is_err());
+            assert!(to_json(UNIX_EPOCH + Duration::from_secs(MAX_SECS + 1)).is_err());
+            assert!(to_json(UNIX_EPOCH + Duration::from_secs(MAX_SECS)).is_ok());
+        }
+    }
+}
+
+#[cfg(test)]
+mod user_spec {
+    use std::time::{Duration, SystemTime};
+
+    use super::{event, user, EventSourced as _, TryEventSourced as _};
+
+    fn user() -> user::User {
+        user::User {
+            id: user::Id(1),
+            name: None,
+            online_since: None,
+            created_at: user::CreationDateTime(SystemTime::UNIX_EPOCH),
+            last_activity_at: user::LastActivityDateTime(SystemTime::UNIX_EPOCH),
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn routes_every_event() {
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(10);
+        let mut user = user();
+
+        user.apply(&user::Event::NameUpdated(event::UserNameUpdated {
+            user_id: user::Id(1),
+            name: Some(user::Name("Alice".into())),
+            at,
+        }));
+        assert_eq!(user.name.as_ref().map(|n| &*n.0), Some("Alice"));
+
+        user.apply(&user::Event::Online(event::UserBecameOnline {
+            user_id: user::Id(1),
+            at,
+        }));
+        assert_eq!(user.online_since, Some(at));
+
+        user.apply(&user::Event::Deleted(event::UserDeleted {
+            user_id: user::Id(1),
+            at: user::DeletionDateTime(at),
+        }));
+        assert_eq!(user.deleted_at.map(|d| d.0), Some(at));
+    }
+
+    #[test]
+    fn rejects_events_violating_lifecycle() {
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(10);
+        let mut user = user();
+
+        let err = user
+            .try_apply(&user::Event::Online(event::UserBecameOnline {
+                user_id: user::Id(2),
+                at,
+            }))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            user::ApplyError::IdMismatch {
+                expected: user::Id(1),
+                actual: user::Id(2),
+            },
+        );
+
+        let err = user
+            .try_apply(&user::Event::Created(event::UserCreated {
+                user_id: user::Id(1),
+                at: user::CreationDateTime(at),
+            }))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            user::ApplyError::AlreadyCreated(user::CreationDateTime(SystemTime::UNIX_EPOCH)),
+        );
+
+        user.try_apply(&user::Event::Deleted(event::UserDeleted {
+            user_id: user::Id(1),
+            at: user::DeletionDateTime(at),
+        }))
+        .unwrap();
+        let err = user
+            .try_apply(&user::Event::Online(event::UserBecameOnline {
+                user_id: user::Id(1),
+                at,
+            }))
+            .unwrap_err();
+        assert_eq!(err, user::ApplyError::Deleted(user::DeletionDateTime(at)));
+        assert_eq!(user.online_since, None);
+    }
+
+    #[test]
+    fn restores_user_from_events() {
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let events = [
+            user::Event::Created(event::UserCreated {
+                user_id: user::Id(1),
+                at: user::CreationDateTime(at(1)),
+            }),
+            user::Event::NameUpdated(event::UserNameUpdated {
+                user_id: user::Id(1),
+                name: Some(user::Name("Alice".into())),
+                at: at(2),
+            }),
+            user::Event::Offline(event::UserBecameOffline {
+                user_id: user::Id(1),
+                at: at(3),
+            }),
+        ];
+
+        let user = user::User::from_events(&events).unwrap();
+        assert_eq!(user.id, user::Id(1));
+        assert_eq!(user.name.as_ref().map(|n| &*n.0), Some("Alice"));
+        assert_eq!(user.created_at, user::CreationDateTime(at(1)));
+        assert_eq!(user.last_activity_at.0, at(3));
+    }
+
+    #[test]
+    fn reports_first_failing_event() {
+        let at = SystemTime::UNIX_EPOCH;
+        let created = || {
+            user::Event::Created(event::UserCreated {
+                user_id: user::Id(1),
+                at: user::CreationDateTime(at),
+            })
+        };
+        let online = |id| {
+            user::Event::Online(event::UserBecameOnline {
+                user_id: user::Id(id),
+                at,
+            })
+        };
+
+        assert_eq!(
+            user::User::from_events([] as [user::Event; 0]).unwrap_err(),
+            user::FromEventsError::Empty,
+        );
+        assert_eq!(
+            user::User::from_events([online(1), created()]).unwrap_err(),
+            user::FromEventsError::NotCreated,
+        );
+        assert_eq!(
+            user::User::from_events([created(), online(1), online(2), online(3)]).unwrap_err(),
+            user::FromEventsError::Apply {
+                index: 2,
+                error: user::ApplyError::IdMismatch {
+                    expected: user::Id(1),
+                    actual: user::Id(2),
+                },
+            },
+        );
+    }
+}
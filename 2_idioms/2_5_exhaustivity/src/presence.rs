@@ -0,0 +1,252 @@
+//! Projection of [`user::Event`]s tracking online presence of users.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+use crate::{event, user, EventSourced};
+
+/// Continuous period of a user being online.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Session {
+    /// Moment the user became online at.
+    pub started_at: SystemTime,
+    /// Moment the user became offline at, if already.
+    pub ended_at: Option<SystemTime>,
+}
+
+impl Session {
+    /// Duration of this [`Session`], with the ongoing one lasting till `now`.
+    ///
+    /// Zero, if the clock went backwards.
+    pub fn duration(&self, now: SystemTime) -> Duration {
+        self.ended_at
+            .unwrap_or(now)
+            .duration_since(self.started_at)
+            .unwrap_or_default()
+    }
+
+    /// Checks whether this [`Session`] is still ongoing.
+    pub fn is_ongoing(&self) -> bool {
+        self.ended_at.is_none()
+    }
+}
+
+/// Event which has no matching counterpart, so was ignored by the [`Presence`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unmatched {
+    /// User became online while being online already.
+    Online(user::Id, SystemTime),
+    /// User became offline without being online.
+    Offline(user::Id, SystemTime),
+}
+
+/// Online presence of users: their [`Session`]s and total online time.
+///
+/// Unmatched online/offline events don't break the projection: a repeated online event
+/// doesn't restart the ongoing [`Session`], and an offline event without an ongoing one
+/// is ignored, both being recorded as [`Unmatched`].
+#[derive(Clone, Debug, Default)]
+pub struct Presence {
+    sessions: HashMap<user::Id, Vec<Session>>,
+    unmatched: Vec<Unmatched>,
+}
+
+impl Presence {
+    /// Creates a new [`Presence`] no events have been applied to.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// [`Session`]s of the user with the given `id`, from the oldest to the newest one.
+    pub fn sessions(&self, id: user::Id) -> &[Session] {
+        self.sessions.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Total time the user with the given `id` has been online for, with the ongoing
+    /// [`Session`] lasting till `now`.
+    pub fn total_online(&self, id: user::Id, now: SystemTime) -> Duration {
+        self.sessions(id).iter().map(|s| s.duration(now)).sum()
+    }
+
+    /// IDs of the users being online at the moment, in ascending order.
+    pub fn online_users(&self) -> Vec<user::Id> {
+        let mut ids = self
+            .sessions
+            .iter()
+            .filter(|(_, s)| s.last().is_some_and(Session::is_ongoing))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        ids.sort_by_key(|id| id.0);
+        ids
+    }
+
+    /// Events ignored for having no matching counterpart, in the order they were applied.
+    pub fn unmatched(&self) -> &[Unmatched] {
+        &self.unmatched
+    }
+
+    /// Ends the ongoing [`Session`] of the user with the given `id`, if any.
+    ///
+    /// Returns `false` if there was no ongoing [`Session`].
+    fn end_session(&mut self, id: user::Id, at: SystemTime) -> bool {
+        match self.sessions.get_mut(&id).and_then(|s| s.last_mut()) {
+            Some(session) if session.is_ongoing() => {
+                session.ended_at = Some(at);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl EventSourced<user::Event> for Presence {
+    fn apply(&mut self, ev: &user::Event) {
+        ev.apply_to(self);
+    }
+}
+
+impl EventSourced<event::UserCreated> for Presence {
+    fn apply(&mut self, _: &event::UserCreated) {}
+}
+
+impl EventSourced<event::UserNameUpdated> for Presence {
+    fn apply(&mut self, _: &event::UserNameUpdated) {}
+}
+
+impl EventSourced<event::UserBecameOnline> for Presence {
+    fn apply(&mut self, ev: &event::UserBecameOnline) {
+        let sessions = self.sessions.entry(ev.user_id).or_default();
+        if sessions.last().is_some_and(Session::is_ongoing) {
+            self.unmatched.push(Unmatched::Online(ev.user_id, ev.at));
+            return;
+        }
+        sessions.push(Session {
+            started_at: ev.at,
+            ended_at: None,
+        });
+    }
+}
+
+impl EventSourced<event::UserBecameOffline> for Presence {
+    fn apply(&mut self, ev: &event::UserBecameOffline) {
+        if !self.end_session(ev.user_id, ev.at) {
+            self.unmatched.push(Unmatched::Offline(ev.user_id, ev.at));
+        }
+    }
+}
+
+impl EventSourced<event::UserDeleted> for Presence {
+    fn apply(&mut self, ev: &event::UserDeleted) {
+        self.end_session(ev.user_id, ev.at.0);
+    }
+}
+
+#[cfg(test)]
+mod presence_spec {
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn online(id: u64, secs: u64) -> user::Event {
+        user::Event::Online(event::UserBecameOnline {
+            user_id: user::Id(id),
+            at: at(secs),
+        })
+    }
+
+    fn offline(id: u64, secs: u64) -> user::Event {
+        user::Event::Offline(event::UserBecameOffline {
+            user_id: user::Id(id),
+            at: at(secs),
+        })
+    }
+
+    fn presence(events: &[user::Event]) -> Presence {
+        let mut presence = Presence::new();
+        for ev in events {
+            presence.apply(ev);
+        }
+        presence
+    }
+
+    #[test]
+    fn sums_online_time_of_sessions() {
+        let presence = presence(&[
+            online(1, 10),
+            online(2, 15),
+            offline(1, 20),
+            online(1, 30),
+            offline(2, 40),
+        ]);
+
+        assert_eq!(
+            presence.sessions(user::Id(1)),
+            [
+                Session {
+                    started_at: at(10),
+                    ended_at: Some(at(20)),
+                },
+                Session {
+                    started_at: at(30),
+                    ended_at: None,
+                },
+            ],
+        );
+        assert_eq!(
+            presence.total_online(user::Id(1), at(35)),
+            Duration::from_secs(15),
+        );
+        assert_eq!(
+            presence.total_online(user::Id(2), at(100)),
+            Duration::from_secs(25),
+        );
+        assert_eq!(presence.online_users(), [user::Id(1)]);
+        assert!(presence.unmatched().is_empty());
+    }
+
+    #[test]
+    fn tolerates_unmatched_events() {
+        let presence = presence(&[
+            offline(1, 5),
+            online(1, 10),
+            online(1, 12),
+            offline(1, 20),
+            offline(1, 25),
+        ]);
+
+        assert_eq!(
+            presence.total_online(user::Id(1), at(100)),
+            Duration::from_secs(10),
+        );
+        assert!(presence.online_users().is_empty());
+        assert_eq!(
+            presence.unmatched(),
+            [
+                Unmatched::Offline(user::Id(1), at(5)),
+                Unmatched::Online(user::Id(1), at(12)),
+                Unmatched::Offline(user::Id(1), at(25)),
+            ],
+        );
+    }
+
+    #[test]
+    fn ends_session_on_deletion() {
+        let presence = presence(&[
+            online(1, 10),
+            user::Event::Deleted(event::UserDeleted {
+                user_id: user::Id(1),
+                at: user::DeletionDateTime(at(15)),
+            }),
+        ]);
+
+        assert!(presence.online_users().is_empty());
+        assert_eq!(
+            presence.total_online(user::Id(1), at(100)),
+            Duration::from_secs(5),
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Processing of [`user::Event`]s interleaved between many [`User`]s.
+
+use std::{collections::HashMap, error::Error, fmt};
+
+use crate::{
+    user::{self, ApplyError, User},
+    TryEventSourced as _,
+};
+
+/// Current state of [`User`]s, routing each [`user::Event`] to the [`User`] it happened to.
+///
+/// [`User`]s are created on [`user::Event::Created`] and iterated in the order of their
+/// creation. Deleted [`User`]s are kept, but reject any further [`user::Event`]s.
+#[derive(Debug, Default)]
+pub struct Users {
+    users: Vec<User>,
+    index: HashMap<user::Id, usize>,
+}
+
+impl Users {
+    /// Creates a new [`Users`] processor with no [`User`]s.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies the `event` to the [`User`] it happened to, creating one on
+    /// [`user::Event::Created`].
+    ///
+    /// Leaves the state untouched if the `event` cannot be applied.
+    pub fn process(&mut self, event: &user::Event) -> Result<(), ProcessError> {
+        let id = event.user_id();
+        let Some(&i) = self.index.get(&id) else {
+            let user::Event::Created(ev) = event else {
+                return Err(ProcessError::Unknown(id));
+            };
+            self.index.insert(id, self.users.len());
+            self.users.push(User::new(ev));
+            return Ok(());
+        };
+        self.users[i]
+            .try_apply(event)
+            .map_err(|error| ProcessError::Apply { id, error })
+    }
+
+    /// Applies all the `events` in order, collecting the ones that cannot be applied along
+    /// with their indices.
+    pub fn process_all<'e, I>(&mut self, events: I) -> Vec<(usize, ProcessError)>
+    where
+        I: IntoIterator<Item = &'e user::Event>,
+    {
+        events
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, ev)| self.process(ev).err().map(|e| (i, e)))
+            .collect()
+    }
+
+    /// [`User`] with the given `id`, if it has been created.
+    pub fn get(&self, id: user::Id) -> Option<&User> {
+        self.index.get(&id).map(|&i| &self.users[i])
+    }
+
+    /// All the created [`User`]s, including deleted ones, in the order of their creation.
+    pub fn iter(&self) -> impl Iterator<Item = &User> {
+        self.users.iter()
+    }
+
+    /// Created [`User`]s which are not deleted, in the order of their creation.
+    pub fn active(&self) -> impl Iterator<Item = &User> {
+        self.iter().filter(|u| u.deleted_at.is_none())
+    }
+
+    /// Number of the created [`User`]s, including deleted ones.
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    /// Checks whether no [`User`]s have been created.
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+}
+
+impl<'u> IntoIterator for &'u Users {
+    type Item = &'u User;
+    type IntoIter = std::slice::Iter<'u, User>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.users.iter()
+    }
+}
+
+/// Reason of a [`user::Event`] being rejected by [`Users::process()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessError {
+    /// [`User`] with the given ID hasn't been created.
+    Unknown(user::Id),
+    /// [`user::Event`] cannot be applied to the existing [`User`], e.g. a deleted one.
+    Apply {
+        /// ID of the [`User`] the [`user::Event`] happened to.
+        id: user::Id,
+        /// Why the [`user::Event`] cannot be applied.
+        error: ApplyError,
+    },
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessError::Unknown(id) => write!(f, "user {} is unknown", id.0),
+            ProcessError::Apply { id, error } => {
+                write!(f, "cannot apply event to user {}: {error}", id.0)
+            }
+        }
+    }
+}
+
+impl Error for ProcessError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ProcessError::Unknown(_) => None,
+            ProcessError::Apply { error, .. } => Some(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod users_spec {
+    use std::time::{Duration, SystemTime};
+
+    use crate::event;
+
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn created(id: u64, secs: u64) -> user::Event {
+        user::Event::Created(event::UserCreated {
+            user_id: user::Id(id),
+            at: user::CreationDateTime(at(secs)),
+        })
+    }
+
+    fn renamed(id: u64, name: &str) -> user::Event {
+        user::Event::NameUpdated(event::UserNameUpdated {
+            user_id: user::Id(id),
+            name: Some(user::Name(name.into())),
+            at: at(10),
+        })
+    }
+
+    fn deleted(id: u64, secs: u64) -> user::Event {
+        user::Event::Deleted(event::UserDeleted {
+            user_id: user::Id(id),
+            at: user::DeletionDateTime(at(secs)),
+        })
+    }
+
+    #[test]
+    fn routes_interleaved_events_by_user() {
+        let mut users = Users::new();
+
+        let rejected = users.process_all(&[
+            created(2, 1),
+            created(1, 2),
+            renamed(1, "Alice"),
+            renamed(2, "Bob"),
+            deleted(2, 3),
+        ]);
+
+        assert!(rejected.is_empty());
+        assert_eq!(users.len(), 2);
+        assert_eq!(
+            users.get(user::Id(1)).unwrap().name,
+            Some(user::Name("Alice".into())),
+        );
+        assert_eq!(
+            users.iter().map(|u| u.id).collect::<Vec<_>>(),
+            [user::Id(2), user::Id(1)],
+        );
+        assert_eq!(
+            users.active().map(|u| u.id).collect::<Vec<_>>(),
+            [user::Id(1)],
+        );
+        assert!(users.get(user::Id(3)).is_none());
+    }
+
+    #[test]
+    fn reports_unknown_and_deleted_users() {
+        let mut users = Users::new();
+
+        let rejected = users.process_all(&[
+            renamed(1, "Alice"),
+            created(1, 1),
+            created(1, 2),
+            deleted(1, 3),
+            renamed(1, "Alice"),
+        ]);
+
+        assert_eq!(
+            rejected,
+            [
+                (0, ProcessError::Unknown(user::Id(1))),
+                (
+                    2,
+                    ProcessError::Apply {
+                        id: user::Id(1),
+                        error: ApplyError::AlreadyCreated(user::CreationDateTime(at(1))),
+                    },
+                ),
+                (
+                    4,
+                    ProcessError::Apply {
+                        id: user::Id(1),
+                        error: ApplyError::Deleted(user::DeletionDateTime(at(3))),
+                    },
+                ),
+            ],
+        );
+        assert_eq!(users.get(user::Id(1)).unwrap().name, None);
+    }
+}
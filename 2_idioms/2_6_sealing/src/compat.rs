@@ -0,0 +1,435 @@
+//! Adapters between [`MyError`] and [`std::error::Error`].
+//!
+//! Both adapters keep the wrapped error as is, and expose its causes as [`StdCause`]s and
+//! [`MyCause`]s, created lazily while walking the `source()` chain. As the cause of one trait
+//! cannot be borrowed as the other one, these nodes delegate to the live causes instead of
+//! capturing them, so the typed causes are reachable via their `get_ref()`, or directly via
+//! [`StdErrorCompat::find_source()`] and [`MyErrorCompat::find_source()`].
+//!
+//! # Examples
+//!
+//! ```rust
+//! use std::{error::Error, fmt};
+//!
+//! use task_2_6::{
+//!     compat::{MyErrorCompat, StdErrorCompat},
+//!     MyError,
+//! };
+//!
+//! #[derive(Debug)]
+//! struct ConfigError(StdErrorCompat<std::num::ParseIntError>);
+//!
+//! impl fmt::Display for ConfigError {
+//!     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+//!         write!(f, "invalid config")
+//!     }
+//! }
+//!
+//! impl MyError for ConfigError {
+//!     fn source(&self) -> Option<&(dyn MyError + 'static)> {
+//!         Some(&self.0)
+//!     }
+//! }
+//!
+//! fn parse(port: &str) -> Result<u16, ConfigError> {
+//!     Ok(port.parse().map_err(|e| ConfigError(StdErrorCompat::new(e)))?)
+//! }
+//!
+//! fn run() -> Result<u16, Box<dyn Error + Send + Sync>> {
+//!     Ok(parse("http").map_err(MyErrorCompat::new)?)
+//! }
+//!
+//! let err = run().unwrap_err();
+//! assert_eq!(err.to_string(), "invalid config");
+//! assert_eq!(
+//!     err.source().unwrap().to_string(),
+//!     "invalid digit found in string",
+//! );
+//!
+//! let compat = err.downcast_ref::<MyErrorCompat<ConfigError>>().unwrap();
+//! let cause = compat
+//!     .find_source::<StdErrorCompat<std::num::ParseIntError>>()
+//!     .unwrap();
+//! assert_eq!(
+//!     *cause.get_ref().kind(),
+//!     std::num::IntErrorKind::InvalidDigit,
+//! );
+//! ```
+
+use std::{
+    error::Error,
+    fmt::{self, Debug, Display},
+    iter, ptr,
+    sync::{Arc, OnceLock},
+};
+
+use crate::my_error::{MyError, Sources};
+
+/// [`std::error::Error`] wrapped as a [`MyError`].
+pub struct StdErrorCompat<E> {
+    error: Arc<E>,
+    source: OnceLock<Option<Box<StdCause>>>,
+}
+
+impl<E: Error + Send + Sync + 'static> StdErrorCompat<E> {
+    /// Wraps the given [`std::error::Error`].
+    pub fn new(error: E) -> Self {
+        Self {
+            error: Arc::new(error),
+            source: OnceLock::new(),
+        }
+    }
+
+    /// Returns the first of the causes of the wrapped error being of the type `T`, if any.
+    pub fn find_source<T: Error + 'static>(&self) -> Option<&T> {
+        std_sources(&*self.error).find_map(|e| e.downcast_ref())
+    }
+}
+
+impl<E> StdErrorCompat<E> {
+    /// Returns a reference to the wrapped error.
+    pub fn get_ref(&self) -> &E {
+        &self.error
+    }
+
+    /// Unwraps the wrapped error.
+    pub fn into_inner(self) -> E {
+        let Self { error, source } = self;
+        // Causes are the only other owners of the wrapped error.
+        drop(source);
+        Arc::into_inner(error).expect("causes don't outlive their adapter")
+    }
+}
+
+impl<E: Error + Send + Sync + 'static> From<E> for StdErrorCompat<E> {
+    fn from(error: E) -> Self {
+        Self::new(error)
+    }
+}
+
+impl<E: Debug> Debug for StdErrorCompat<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&*self.error, f)
+    }
+}
+
+impl<E: Display> Display for StdErrorCompat<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&*self.error, f)
+    }
+}
+
+impl<E: Error + Send + Sync + 'static> MyError for StdErrorCompat<E> {
+    fn source(&self) -> Option<&(dyn MyError + 'static)> {
+        self.source
+            .get_or_init(|| StdCause::nth(&(self.error.clone() as _), 1))
+            .as_deref()
+            .map(|c| c as _)
+    }
+}
+
+/// [`MyError`] wrapped as a [`std::error::Error`].
+pub struct MyErrorCompat<E> {
+    error: Arc<E>,
+    source: OnceLock<Option<Box<MyCause>>>,
+}
+
+impl<E: MyError + Send + Sync + 'static> MyErrorCompat<E> {
+    /// Wraps the given [`MyError`].
+    pub fn new(error: E) -> Self {
+        Self {
+            error: Arc::new(error),
+            source: OnceLock::new(),
+        }
+    }
+
+    /// Returns the first of the causes of the wrapped error being of the type `T`, if any.
+    pub fn find_source<T: MyError + 'static>(&self) -> Option<&T> {
+        (&*self.error as &(dyn MyError + 'static)).find_source()
+    }
+}
+
+impl<E> MyErrorCompat<E> {
+    /// Returns a reference to the wrapped error.
+    pub fn get_ref(&self) -> &E {
+        &self.error
+    }
+
+    /// Unwraps the wrapped error.
+    pub fn into_inner(self) -> E {
+        let Self { error, source } = self;
+        // Causes are the only other owners of the wrapped error.
+        drop(source);
+        Arc::into_inner(error).expect("causes don't outlive their adapter")
+    }
+}
+
+impl<E: MyError + Send + Sync + 'static> From<E> for MyErrorCompat<E> {
+    fn from(error: E) -> Self {
+        Self::new(error)
+    }
+}
+
+impl<E: Debug> Debug for MyErrorCompat<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&*self.error, f)
+    }
+}
+
+impl<E: Display> Display for MyErrorCompat<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&*self.error, f)
+    }
+}
+
+impl<E: MyError + Send + Sync + 'static> Error for MyErrorCompat<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source
+            .get_or_init(|| MyCause::nth(&(self.error.clone() as _), 1))
+            .as_deref()
+            .map(|c| c as _)
+    }
+}
+
+/// Cause of an error wrapped into a [`StdErrorCompat`], seen as a [`MyError`].
+///
+/// Refers to the cause by its depth in the `source()` chain of the wrapped error, so the
+/// causes are expected not to change while being wrapped.
+pub struct StdCause {
+    root: Arc<dyn Error + Send + Sync>,
+    depth: usize,
+    source: OnceLock<Option<Box<StdCause>>>,
+}
+
+impl StdCause {
+    /// Creates the node of the cause at the given `depth`, if the chain is that deep.
+    fn nth(root: &Arc<dyn Error + Send + Sync>, depth: usize) -> Option<Box<Self>> {
+        std_sources(&**root).nth(depth - 1)?;
+        Some(Box::new(Self {
+            root: root.clone(),
+            depth,
+            source: OnceLock::new(),
+        }))
+    }
+
+    /// Returns a reference to the cause itself.
+    pub fn get_ref(&self) -> &(dyn Error + 'static) {
+        std_sources(&*self.root)
+            .nth(self.depth - 1)
+            .expect("causes don't change while being wrapped")
+    }
+}
+
+impl Debug for StdCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.get_ref(), f)
+    }
+}
+
+impl Display for StdCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self.get_ref(), f)
+    }
+}
+
+impl MyError for StdCause {
+    fn source(&self) -> Option<&(dyn MyError + 'static)> {
+        self.source
+            .get_or_init(|| Self::nth(&self.root, self.depth + 1))
+            .as_deref()
+            .map(|c| c as _)
+    }
+}
+
+/// Cause of an error wrapped into a [`MyErrorCompat`], seen as a [`std::error::Error`].
+///
+/// Refers to the cause by its depth in the `source()` chain of the wrapped error, so the
+/// causes are expected not to change while being wrapped.
+pub struct MyCause {
+    root: Arc<dyn MyError + Send + Sync>,
+    depth: usize,
+    source: OnceLock<Option<Box<MyCause>>>,
+}
+
+impl MyCause {
+    /// Creates the node of the cause at the given `depth`, if the chain is that deep.
+    fn nth(root: &Arc<dyn MyError + Send + Sync>, depth: usize) -> Option<Box<Self>> {
+        Sources::new(&**root).nth(depth - 1)?;
+        Some(Box::new(Self {
+            root: root.clone(),
+            depth,
+            source: OnceLock::new(),
+        }))
+    }
+
+    /// Returns a reference to the cause itself.
+    pub fn get_ref(&self) -> &(dyn MyError + 'static) {
+        Sources::new(&*self.root)
+            .nth(self.depth - 1)
+            .expect("causes don't change while being wrapped")
+    }
+}
+
+impl Debug for MyCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.get_ref(), f)
+    }
+}
+
+impl Display for MyCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self.get_ref(), f)
+    }
+}
+
+impl Error for MyCause {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source
+            .get_or_init(|| Self::nth(&self.root, self.depth + 1))
+            .as_deref()
+            .map(|c| c as _)
+    }
+}
+
+/// Iterates over the [`std::error::Error::source()`] chain of the given `error`, excluding
+/// the error itself, and stopping before the first cause met in the chain already.
+fn std_sources<'a>(
+    error: &'a (dyn Error + 'static),
+) -> impl Iterator<Item = &'a (dyn Error + 'static)> {
+    let mut seen = vec![error as *const dyn Error];
+    iter::successors(error.source(), |&e| e.source()).take_while(move |&e| {
+        let new = !seen.iter().any(|&s| ptr::eq(s, e));
+        seen.push(e);
+        new
+    })
+}
+
+#[cfg(test)]
+mod compat_spec {
+    use super::*;
+
+    /// Error with an optional source, being both a [`MyError`] and a [`std::error::Error`].
+    #[derive(Debug)]
+    struct Layered {
+        message: &'static str,
+        source: Option<Box<Layered>>,
+    }
+
+    impl Layered {
+        fn chain(messages: &[&'static str]) -> Self {
+            let (message, rest) = messages.split_first().unwrap();
+            Self {
+                message,
+                source: (!rest.is_empty()).then(|| Box::new(Self::chain(rest))),
+            }
+        }
+    }
+
+    impl Display for Layered {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.message)
+        }
+    }
+
+    impl MyError for Layered {
+        fn source(&self) -> Option<&(dyn MyError + 'static)> {
+            self.source.as_deref().map(|e| e as _)
+        }
+    }
+
+    impl Error for Layered {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.source.as_deref().map(|e| e as _)
+        }
+    }
+
+    /// Error being its own source.
+    #[derive(Debug)]
+    struct Ouroboros(u8);
+
+    impl Display for Ouroboros {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "ouroboros #{}", self.0)
+        }
+    }
+
+    impl Error for Ouroboros {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            static SELF: Ouroboros = Ouroboros(1);
+            Some(&SELF)
+        }
+    }
+
+    fn std_messages(error: &dyn Error) -> Vec<String> {
+        let mut messages = vec![error.to_string()];
+        let mut next = error.source();
+        while let Some(e) = next {
+            messages.push(e.to_string());
+            next = e.source();
+        }
+        messages
+    }
+
+    fn my_messages(error: &dyn MyError) -> Vec<String> {
+        [error.to_string()]
+            .into_iter()
+            .chain(Sources::new(error).map(|e| e.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_whole_std_error_chain() {
+        let err = StdErrorCompat::new(Layered::chain(&["a", "b", "c", "d"]));
+
+        assert_eq!(my_messages(&err), ["a", "b", "c", "d"]);
+        assert_eq!(
+            format!("{:?}", err.source().unwrap()),
+            format!("{:?}", Layered::chain(&["b", "c", "d"])),
+        );
+    }
+
+    #[test]
+    fn keeps_whole_my_error_chain() {
+        let err = MyErrorCompat::new(Layered::chain(&["a", "b", "c", "d"]));
+
+        assert_eq!(std_messages(&err), ["a", "b", "c", "d"]);
+        assert_eq!(err.get_ref().message, "a");
+    }
+
+    #[test]
+    fn survives_round_trip() {
+        let err = MyErrorCompat::new(StdErrorCompat::new(Layered::chain(&["a", "b", "c"])));
+
+        assert_eq!(std_messages(&err), ["a", "b", "c"]);
+        assert_eq!(err.get_ref().find_source::<Layered>().unwrap().message, "b",);
+    }
+
+    #[test]
+    fn finds_typed_std_causes_across_adapter() {
+        let err = StdErrorCompat::new(Layered::chain(&["a", "b", "c"]));
+
+        let cause = MyError::source(&err).unwrap().downcast_ref::<StdCause>();
+        let cause = cause.unwrap().get_ref().downcast_ref::<Layered>().unwrap();
+        assert!(ptr::eq(cause, err.get_ref().source.as_deref().unwrap()));
+        assert_eq!(err.find_source::<Layered>().unwrap().message, "b");
+        assert_eq!(err.into_inner().message, "a");
+    }
+
+    #[test]
+    fn finds_typed_my_causes_across_adapter() {
+        let err = MyErrorCompat::new(Layered::chain(&["a", "b", "c"]));
+
+        let cause = err.source().unwrap().source().unwrap();
+        let cause = cause.downcast_ref::<MyCause>().unwrap().get_ref();
+        assert_eq!(cause.downcast_ref::<Layered>().unwrap().message, "c");
+        assert_eq!(err.find_source::<Layered>().unwrap().message, "b");
+        assert_eq!(err.into_inner().message, "a");
+    }
+
+    #[test]
+    fn cuts_cyclic_std_error_chain() {
+        let err = StdErrorCompat::new(Ouroboros(0));
+
+        assert_eq!(my_messages(&err), ["ouroboros #0", "ouroboros #1"]);
+    }
+}
@@ -0,0 +1,201 @@
+//! [`MyError`]s carrying a [`Backtrace`] and human-readable context.
+
+use std::{
+    any::Any,
+    backtrace::{Backtrace, BacktraceStatus},
+    fmt::{self, Debug, Display},
+};
+
+use crate::{MyError, Report};
+
+/// [`MyError`] with a [`Backtrace`] captured on its creation and a stack of context
+/// messages, describing what was being done when the error happened.
+///
+/// The context messages, from the latest to the earliest one, precede the wrapped error in
+/// the [`MyError::source()`] chain, so the [`Traced`] itself is displayed as its latest
+/// context message.
+///
+/// The [`Backtrace`] is captured via [`Backtrace::capture()`], so depends on the
+/// `RUST_BACKTRACE`/`RUST_LIB_BACKTRACE` environment variables.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::fmt;
+///
+/// use task_2_6::{context::ResultExt as _, MyError, Report};
+///
+/// #[derive(Debug)]
+/// struct NotFound;
+///
+/// impl fmt::Display for NotFound {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         write!(f, "file not found")
+///     }
+/// }
+///
+/// impl MyError for NotFound {}
+///
+/// let path = "app.toml";
+/// let err = Err::<(), _>(NotFound)
+///     .with_context(|| format!("while reading `{path}`"))
+///     .context("while loading config")
+///     .unwrap_err();
+///
+/// assert_eq!(
+///     Report::new(&err).to_string(),
+///     "while loading config: while reading `app.toml`: file not found",
+/// );
+/// assert_eq!(
+///     err.contexts().collect::<Vec<_>>(),
+///     ["while loading config", "while reading `app.toml`"],
+/// );
+/// assert!(err.root().is::<NotFound>());
+///
+/// let err: &dyn MyError = &err;
+/// assert!(err.find_source::<NotFound>().is_some());
+/// ```
+pub struct Traced {
+    top: Layer,
+    backtrace: Backtrace,
+}
+
+impl Traced {
+    /// Wraps the given `error`, capturing the [`Backtrace`].
+    ///
+    /// If the `error` is a [`Traced`] already, returns it as is.
+    pub fn new<E>(error: E) -> Self
+    where
+        E: MyError + Send + Sync + 'static,
+    {
+        let mut error = Some(error);
+        if let Some(traced) = (&mut error as &mut dyn Any).downcast_mut::<Option<Self>>() {
+            return traced.take().unwrap();
+        }
+        Self {
+            top: Layer::Error(Box::new(error.unwrap())),
+            backtrace: Backtrace::capture(),
+        }
+    }
+
+    /// Puts the given `context` message on top of this [`Traced`] error.
+    #[must_use]
+    pub fn context<C: Display>(self, context: C) -> Self {
+        Self {
+            top: Layer::Context {
+                message: context.to_string(),
+                source: Box::new(self.top),
+            },
+            backtrace: self.backtrace,
+        }
+    }
+
+    /// Context messages of this [`Traced`] error, from the latest to the earliest one.
+    pub fn contexts(&self) -> impl Iterator<Item = &str> {
+        let mut next = Some(&self.top);
+        std::iter::from_fn(move || match next? {
+            Layer::Context { message, source } => {
+                next = Some(source);
+                Some(message.as_str())
+            }
+            Layer::Error(_) => None,
+        })
+    }
+
+    /// The wrapped error, without any context.
+    pub fn root(&self) -> &(dyn MyError + 'static) {
+        let mut layer = &self.top;
+        loop {
+            match layer {
+                Layer::Context { source, .. } => layer = source,
+                Layer::Error(e) => return &**e,
+            }
+        }
+    }
+
+    /// [`Backtrace`] captured when the wrapped error was created.
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+}
+
+impl Debug for Traced {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Report::new(self).pretty(true))?;
+        if self.backtrace.status() == BacktraceStatus::Captured {
+            write!(f, "\n\nStack backtrace:\n{}", self.backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Traced {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.top, f)
+    }
+}
+
+impl MyError for Traced {
+    fn source(&self) -> Option<&(dyn MyError + 'static)> {
+        self.top.source()
+    }
+}
+
+/// Layer of a [`Traced`] error: either a context message or the wrapped error itself.
+#[derive(Debug)]
+enum Layer {
+    Context { message: String, source: Box<Layer> },
+    Error(Box<dyn MyError + Send + Sync>),
+}
+
+impl Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layer::Context { message, .. } => f.write_str(message),
+            Layer::Error(e) => Display::fmt(e, f),
+        }
+    }
+}
+
+impl MyError for Layer {
+    fn source(&self) -> Option<&(dyn MyError + 'static)> {
+        match self {
+            // The wrapped error is exposed directly, so it can be downcast.
+            Layer::Context { source, .. } => match &**source {
+                Layer::Error(e) => Some(&**e),
+                layer => Some(layer),
+            },
+            Layer::Error(e) => e.source(),
+        }
+    }
+}
+
+/// Extension trait for attaching context to a [`Result`] with a [`MyError`].
+pub trait ResultExt<T> {
+    /// Wraps the error into a [`Traced`] one, if not yet, and puts the given `context` on
+    /// top of it.
+    fn context<C: Display>(self, context: C) -> Result<T, Traced>;
+
+    /// Same as [`ResultExt::context()`], but builds the context lazily, only on error.
+    fn with_context<C, F>(self, context: F) -> Result<T, Traced>
+    where
+        C: Display,
+        F: FnOnce() -> C;
+}
+
+impl<T, E> ResultExt<T> for Result<T, E>
+where
+    E: MyError + Send + Sync + 'static,
+{
+    fn context<C: Display>(self, context: C) -> Result<T, Traced> {
+        self.map_err(|e| Traced::new(e).context(context))
+    }
+
+    fn with_context<C, F>(self, context: F) -> Result<T, Traced>
+    where
+        C: Display,
+        F: FnOnce() -> C,
+    {
+        self.map_err(|e| Traced::new(e).context(context()))
+    }
+}
@@ -1,4 +1,7 @@
+pub mod compat;
+pub mod context;
 pub mod my_error;
 pub mod my_iterator_ext;
+pub mod report;
 
-pub use self::{my_error::MyError, my_iterator_ext::MyIteratorExt};
+pub use self::{my_error::MyError, my_iterator_ext::MyIteratorExt, report::Report};
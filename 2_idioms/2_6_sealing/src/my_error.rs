@@ -4,6 +4,7 @@
 use std::{
     any::TypeId,
     fmt::{Debug, Display},
+    ptr,
 };
 
 /// Basic expectations for error values.
@@ -65,9 +66,10 @@ pub trait MyError: Debug + Display {
 
     /// Gets the `TypeId` of `self`.
     ///
-    /// __This is memory-unsafe to override in user code.__
+    /// __This is memory-unsafe to override in user code__, so it's sealed by requiring the
+    /// [`private::Internal`] argument nameable in this module only.
     #[doc(hidden)]
-    fn type_id(&self) -> TypeId
+    fn type_id(&self, _: private::Internal) -> TypeId
     where
         Self: 'static,
     {
@@ -75,8 +77,160 @@ pub trait MyError: Debug + Display {
     }
 }
 
-impl<'a, T: MyError + ?Sized> MyError for &'a T {
+impl<T: MyError + ?Sized> MyError for &T {
     fn source(&self) -> Option<&(dyn MyError + 'static)> {
         MyError::source(&**self)
     }
 }
+
+impl dyn MyError + 'static {
+    /// Checks whether this error is of the type `T`.
+    pub fn is<T: MyError + 'static>(&self) -> bool {
+        MyError::type_id(self, private::Internal) == TypeId::of::<T>()
+    }
+
+    /// Returns a reference to this error as the type `T`, if it's of that type.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::fmt;
+    ///
+    /// use task_2_6::MyError;
+    ///
+    /// #[derive(Debug)]
+    /// struct NotFound(&'static str);
+    ///
+    /// impl fmt::Display for NotFound {
+    ///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    ///         write!(f, "`{}` is not found", self.0)
+    ///     }
+    /// }
+    ///
+    /// impl MyError for NotFound {}
+    ///
+    /// let err: Box<dyn MyError> = Box::new(NotFound("app.toml"));
+    ///
+    /// assert!(err.is::<NotFound>());
+    /// assert_eq!(err.downcast_ref::<NotFound>().unwrap().0, "app.toml");
+    /// ```
+    pub fn downcast_ref<T: MyError + 'static>(&self) -> Option<&T> {
+        if self.is::<T>() {
+            // SAFETY: `type_id()` cannot be overridden outside this module, so `self` is
+            //         checked to be `T` right above.
+            Some(unsafe { &*(self as *const dyn MyError).cast::<T>() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to this error as the type `T`, if it's of that type.
+    pub fn downcast_mut<T: MyError + 'static>(&mut self) -> Option<&mut T> {
+        if self.is::<T>() {
+            // SAFETY: `type_id()` cannot be overridden outside this module, so `self` is
+            //         checked to be `T` right above.
+            Some(unsafe { &mut *(self as *mut dyn MyError).cast::<T>() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the first of [`Sources`] of this error being of the type `T`, if any.
+    ///
+    /// The error itself is not considered, use [`downcast_ref()`] for that.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::fmt;
+    ///
+    /// use task_2_6::MyError;
+    ///
+    /// #[derive(Debug)]
+    /// struct Wrapped<E>(E);
+    ///
+    /// impl<E: MyError> fmt::Display for Wrapped<E> {
+    ///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    ///         write!(f, "wrapped")
+    ///     }
+    /// }
+    ///
+    /// impl<E: MyError + 'static> MyError for Wrapped<E> {
+    ///     fn source(&self) -> Option<&(dyn MyError + 'static)> {
+    ///         Some(&self.0)
+    ///     }
+    /// }
+    ///
+    /// #[derive(Debug)]
+    /// struct Timeout(u64);
+    ///
+    /// impl fmt::Display for Timeout {
+    ///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    ///         write!(f, "timed out after {}s", self.0)
+    ///     }
+    /// }
+    ///
+    /// impl MyError for Timeout {}
+    ///
+    /// let err: &dyn MyError = &Wrapped(Wrapped(Timeout(30)));
+    ///
+    /// assert_eq!(err.find_source::<Timeout>().unwrap().0, 30);
+    /// assert!(err.find_source::<Wrapped<Timeout>>().is_some());
+    /// assert!(err.find_source::<Wrapped<Wrapped<Timeout>>>().is_none());
+    /// ```
+    ///
+    /// [`downcast_ref()`]: Self::downcast_ref
+    pub fn find_source<T: MyError + 'static>(&self) -> Option<&T> {
+        Sources::new(self).find_map(|e| e.downcast_ref())
+    }
+}
+
+/// Iterator over the [`MyError::source()`] chain of an error, excluding the error itself.
+///
+/// Stops before the first cause that has been met in the chain already, so terminates
+/// even on cyclic chains.
+#[derive(Clone, Debug)]
+pub struct Sources<'a> {
+    next: Option<&'a (dyn MyError + 'static)>,
+    seen: Vec<*const (dyn MyError + 'a)>,
+    cyclic: bool,
+}
+
+impl<'a> Sources<'a> {
+    /// Creates a new [`Sources`] iterator over the causes of the given `error`.
+    pub fn new(error: &'a dyn MyError) -> Self {
+        Self {
+            next: error.source(),
+            seen: vec![error],
+            cyclic: false,
+        }
+    }
+
+    /// Checks whether the chain has been detected to be cyclic.
+    ///
+    /// Only meaningful once the iterator is exhausted.
+    pub fn is_cyclic(&self) -> bool {
+        self.cyclic
+    }
+}
+
+impl<'a> Iterator for Sources<'a> {
+    type Item = &'a (dyn MyError + 'static);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next.take()?;
+        if self.seen.iter().any(|&e| ptr::eq(e, current)) {
+            self.cyclic = true;
+            return None;
+        }
+        self.seen.push(current);
+        self.next = current.source();
+        Some(current)
+    }
+}
+
+mod private {
+    /// Argument sealing the [`MyError::type_id()`](super::MyError::type_id) method.
+    #[derive(Debug)]
+    pub struct Internal;
+}
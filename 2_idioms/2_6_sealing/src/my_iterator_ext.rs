@@ -6,7 +6,7 @@
 
 use std::fmt;
 
-use self::format::{Format, FormatWith};
+use self::format::{Format, FormatCloned, FormatWith, FormatWithCloned};
 
 /// Extension trait for an [`Iterator`].
 pub trait MyIteratorExt: Iterator {
@@ -15,7 +15,8 @@ pub trait MyIteratorExt: Iterator {
     /// All elements are formatted (any formatting trait)
     /// with `sep` inserted between each element.
     ///
-    /// **Panics** if the formatter helper is formatted more than once.
+    /// **Panics** if the formatter helper is formatted more than once, see
+    /// [`.format_cloned()`](MyIteratorExt::format_cloned) for the one that doesn't.
     ///
     /// ```rust
     /// use task_2_6::MyIteratorExt as _;
@@ -25,7 +26,7 @@ pub trait MyIteratorExt: Iterator {
     ///     format!("{:.2}", data.iter().format(", ")),
     ///            "1.10, 2.72, -3.00");
     /// ```
-    fn format(self, sep: &str) -> Format<Self>
+    fn format(self, sep: &str) -> Format<'_, Self>
     where
         Self: Sized,
     {
@@ -43,7 +44,9 @@ pub trait MyIteratorExt: Iterator {
     /// Using `&format_args!(...)` is the most versatile way to apply custom
     /// element formatting. The callback can be called multiple times if needed.
     ///
-    /// **Panics** if the formatter helper is formatted more than once.
+    /// **Panics** if the formatter helper is formatted more than once, see
+    /// [`.format_with_cloned()`](MyIteratorExt::format_with_cloned) for the one that
+    /// doesn't.
     ///
     /// ```rust
     /// use task_2_6::MyIteratorExt as _;
@@ -61,13 +64,56 @@ pub trait MyIteratorExt: Iterator {
     /// });
     /// assert_eq!(matrix_formatter.to_string(), "1, 2, 3\n4, 5, 6");
     /// ```
-    fn format_with<F>(self, sep: &str, format: F) -> FormatWith<Self, F>
+    fn format_with<F>(self, sep: &str, format: F) -> FormatWith<'_, Self, F>
     where
         Self: Sized,
         F: FnMut(Self::Item, &mut dyn FnMut(&dyn fmt::Display) -> fmt::Result) -> fmt::Result,
     {
         format::new_format(self, sep, format)
     }
+
+    /// Format all iterator elements, separated by `sep`, any number of times.
+    ///
+    /// Same as [`.format()`](MyIteratorExt::format), but formats a clone of the
+    /// iterator each time, so never panics.
+    ///
+    /// ```rust
+    /// use task_2_6::MyIteratorExt as _;
+    ///
+    /// let data = [1.1, 2.71828, -3.];
+    /// let data_formatter = data.iter().format_cloned(", ");
+    /// assert_eq!(format!("{:.2}", data_formatter), "1.10, 2.72, -3.00");
+    /// assert_eq!(format!("{:?}", data_formatter), "1.1, 2.71828, -3.0");
+    /// ```
+    fn format_cloned(self, sep: &str) -> FormatCloned<'_, Self>
+    where
+        Self: Sized + Clone,
+    {
+        format::new_format_cloned(self, sep)
+    }
+
+    /// Format all iterator elements, separated by `sep`, any number of times.
+    ///
+    /// Same as [`.format_with()`](MyIteratorExt::format_with), but formats a clone of
+    /// the iterator with a clone of the `format` closure each time, so never panics.
+    ///
+    /// ```rust
+    /// use task_2_6::MyIteratorExt as _;
+    ///
+    /// let data = [1.1, 2.71828, -3.];
+    /// let data_formatter =
+    ///     data.iter().format_with_cloned(", ", |elt, f| f(&format_args!("{:.2}", elt)));
+    /// assert_eq!(data_formatter.to_string(), "1.10, 2.72, -3.00");
+    /// assert_eq!(data_formatter.to_string(), "1.10, 2.72, -3.00");
+    /// ```
+    fn format_with_cloned<F>(self, sep: &str, format: F) -> FormatWithCloned<'_, Self, F>
+    where
+        Self: Sized + Clone,
+        F: FnMut(Self::Item, &mut dyn FnMut(&dyn fmt::Display) -> fmt::Result) -> fmt::Result
+            + Clone,
+    {
+        format::new_format_with_cloned(self, sep, format)
+    }
 }
 
 impl<T> MyIteratorExt for T where T: Iterator {}
@@ -102,6 +148,30 @@ mod format {
         inner: RefCell<Option<I>>,
     }
 
+    /// Format all iterator elements lazily, separated by `sep`, any number of times.
+    ///
+    /// Each formatting goes over a clone of the iterator.
+    ///
+    /// See [`.format_with_cloned()`](crate::MyIteratorExt::format_with_cloned) for more
+    /// information.
+    #[derive(Clone)]
+    pub struct FormatWithCloned<'a, I, F> {
+        sep: &'a str,
+        iter: I,
+        format: F,
+    }
+
+    /// Format all iterator elements lazily, separated by `sep`, any number of times.
+    ///
+    /// Each formatting goes over a clone of the iterator.
+    ///
+    /// See [`.format_cloned()`](crate::MyIteratorExt::format_cloned) for more information.
+    #[derive(Clone)]
+    pub struct FormatCloned<'a, I> {
+        sep: &'a str,
+        iter: I,
+    }
+
     pub fn new_format<I, F>(iter: I, separator: &str, f: F) -> FormatWith<'_, I, F>
     where
         I: Iterator,
@@ -123,6 +193,32 @@ mod format {
         }
     }
 
+    pub fn new_format_with_cloned<I, F>(
+        iter: I,
+        separator: &str,
+        f: F,
+    ) -> FormatWithCloned<'_, I, F>
+    where
+        I: Iterator + Clone,
+        F: FnMut(I::Item, &mut dyn FnMut(&dyn fmt::Display) -> fmt::Result) -> fmt::Result + Clone,
+    {
+        FormatWithCloned {
+            sep: separator,
+            iter,
+            format: f,
+        }
+    }
+
+    pub fn new_format_cloned<I>(iter: I, separator: &str) -> FormatCloned<'_, I>
+    where
+        I: Iterator + Clone,
+    {
+        FormatCloned {
+            sep: separator,
+            iter,
+        }
+    }
+
     impl<'a, I, F> fmt::Display for FormatWith<'a, I, F>
     where
         I: Iterator,
@@ -173,6 +269,16 @@ mod format {
         }
     }
 
+    impl<I, F> fmt::Display for FormatWithCloned<'_, I, F>
+    where
+        I: Iterator + Clone,
+        F: FnMut(I::Item, &mut dyn FnMut(&dyn fmt::Display) -> fmt::Result) -> fmt::Result + Clone,
+    {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            new_format(self.iter.clone(), self.sep, self.format.clone()).fmt(f)
+        }
+    }
+
     macro_rules! impl_format {
         ($($fmt_trait:ident)*) => {
             $(
@@ -185,6 +291,16 @@ mod format {
                         self.format(f, fmt::$fmt_trait::fmt)
                     }
                 }
+
+                impl<'a, I> fmt::$fmt_trait for FormatCloned<'a, I>
+                where
+                    I: Iterator + Clone,
+                    I::Item: fmt::$fmt_trait,
+                {
+                    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                        new_format_default(self.iter.clone(), self.sep).format(f, fmt::$fmt_trait::fmt)
+                    }
+                }
             )*
         }
     }
@@ -0,0 +1,195 @@
+//! Human- and machine-readable reports of [`MyError`]s along with their causes.
+
+use std::fmt::{self, Write as _};
+
+use crate::my_error::{MyError, Sources};
+
+/// Report of an error along with all its [`MyError::source()`]s.
+///
+/// Renders the error on a single line, with the causes separated by `: `, or, if
+/// [`pretty`](Report::pretty), on multiple lines, with the causes listed as numbered
+/// "Caused by" entries. A cyclic chain of causes is cut at the first repeated cause.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::fmt;
+///
+/// use task_2_6::{MyError, Report};
+///
+/// #[derive(Debug)]
+/// struct ConfigError(IoError);
+///
+/// impl fmt::Display for ConfigError {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         write!(f, "cannot load config")
+///     }
+/// }
+///
+/// impl MyError for ConfigError {
+///     fn source(&self) -> Option<&(dyn MyError + 'static)> {
+///         Some(&self.0)
+///     }
+/// }
+///
+/// #[derive(Debug)]
+/// struct IoError;
+///
+/// impl fmt::Display for IoError {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         write!(f, "file \"app.toml\" not found")
+///     }
+/// }
+///
+/// impl MyError for IoError {}
+///
+/// let err = ConfigError(IoError);
+///
+/// assert_eq!(
+///     Report::new(&err).to_string(),
+///     "cannot load config: file \"app.toml\" not found",
+/// );
+/// assert_eq!(
+///     Report::new(&err).pretty(true).to_string(),
+///     "cannot load config\n\
+///      \n\
+///      Caused by:\n    \
+///         0: file \"app.toml\" not found",
+/// );
+/// assert_eq!(
+///     Report::new(&err).json().to_string(),
+///     r#"{"error":"cannot load config","causes":["file \"app.toml\" not found"],"cyclic":false}"#,
+/// );
+/// ```
+///
+/// Cyclic chains are reported as such:
+///
+/// ```rust
+/// use std::fmt;
+///
+/// use task_2_6::{MyError, Report};
+///
+/// #[derive(Debug)]
+/// struct Ouroboros(u8);
+///
+/// impl fmt::Display for Ouroboros {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         write!(f, "ouroboros")
+///     }
+/// }
+///
+/// impl MyError for Ouroboros {
+///     fn source(&self) -> Option<&(dyn MyError + 'static)> {
+///         static SELF: Ouroboros = Ouroboros(0);
+///         Some(&SELF)
+///     }
+/// }
+///
+/// assert_eq!(
+///     Report::new(&Ouroboros(0)).to_string(),
+///     "ouroboros: ouroboros: <cycle detected>",
+/// );
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Report<'a> {
+    error: &'a dyn MyError,
+    pretty: bool,
+}
+
+impl<'a> Report<'a> {
+    /// Creates a new single-line [`Report`] of the given `error`.
+    pub fn new(error: &'a dyn MyError) -> Self {
+        Self {
+            error,
+            pretty: false,
+        }
+    }
+
+    /// Sets whether this [`Report`] should be rendered on multiple lines.
+    #[must_use]
+    pub fn pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+
+    /// Renders this [`Report`] as a single-line JSON object instead.
+    ///
+    /// The object has the `error` message, the `causes` messages array and the `cyclic`
+    /// flag.
+    pub fn json(self) -> JsonReport<'a> {
+        JsonReport(self.error)
+    }
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+
+        let mut sources = Sources::new(self.error);
+        if self.pretty {
+            for (i, cause) in sources.by_ref().enumerate() {
+                if i == 0 {
+                    write!(f, "\n\nCaused by:")?;
+                }
+                write!(f, "\n{i:>5}: ")?;
+                // Indent multi-line messages to the start of the first one.
+                for (n, line) in cause.to_string().lines().enumerate() {
+                    if n > 0 {
+                        write!(f, "\n       ")?;
+                    }
+                    f.write_str(line)?;
+                }
+            }
+            if sources.is_cyclic() {
+                write!(f, "\n       <cycle detected>")?;
+            }
+        } else {
+            for cause in sources.by_ref() {
+                write!(f, ": {cause}")?;
+            }
+            if sources.is_cyclic() {
+                write!(f, ": <cycle detected>")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// [`Report`] rendered as a single-line JSON object.
+///
+/// See [`Report::json()`] for more information.
+#[derive(Clone, Copy, Debug)]
+pub struct JsonReport<'a>(&'a dyn MyError);
+
+impl fmt::Display for JsonReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("{\"error\":")?;
+        write_json_str(f, self.0)?;
+        f.write_str(",\"causes\":[")?;
+        let mut sources = Sources::new(self.0);
+        for (i, cause) in sources.by_ref().enumerate() {
+            if i > 0 {
+                f.write_char(',')?;
+            }
+            write_json_str(f, cause)?;
+        }
+        write!(f, "],\"cyclic\":{}}}", sources.is_cyclic())
+    }
+}
+
+/// Writes the given `value` as a JSON string literal.
+fn write_json_str(f: &mut fmt::Formatter<'_>, value: impl fmt::Display) -> fmt::Result {
+    f.write_char('"')?;
+    for c in value.to_string().chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", u32::from(c))?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}